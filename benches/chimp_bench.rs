@@ -1,8 +1,9 @@
-use chimp_lib::{chimp, Encode};
-use criterion::{criterion_group, criterion_main, Criterion};
+use chimp_lib::bitstream::InputBitStream;
+use chimp_lib::{aligned, chimp, chimpn, decimal, delta, gorilla, timestamp, Decode, Encode};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use std::time::Duration;
 
-fn chimp_enc(c: &mut Criterion) {
+fn city_temps() -> Vec<f64> {
     let reader = csv::Reader::from_path("datasets/city_temperature.csv");
     let mut values: Vec<f64> = Vec::new();
 
@@ -12,10 +13,138 @@ fn chimp_enc(c: &mut Criterion) {
         let val = val.parse::<f64>().unwrap();
         values.push(val);
     }
+    values
+}
+
+fn chimp_enc(c: &mut Criterion) {
+    let values = city_temps();
 
     c.bench_function("encode city temps", |b| {
         b.iter(|| {
-            let _: chimp::Encoder = chimp::Encoder::encode_vec(&values);
+            let mut encoder: chimp::Encoder = chimp::Encoder::new();
+            encoder.encode_slice(&values);
+            encoder
+        })
+    });
+}
+
+fn encode(mut encoder: impl Encode<Sink = Vec<u64>>, values: &[f64]) -> Box<[u64]> {
+    encoder.encode_slice(values);
+    encoder.close().0
+}
+
+// decodes everything and xors it together so the loop can't be optimized away
+fn decode_sum(mut decoder: impl Decode<f64>) -> u64 {
+    let mut sum = 0;
+    while let Ok(bits) = decoder.get_next() {
+        sum ^= bits;
+    }
+    sum
+}
+
+fn bitstream_dec(c: &mut Criterion) {
+    let values = city_temps();
+    let chimp = encode(chimp::Encoder::new(), &values);
+    let chimpn = encode(chimpn::Encoder::new(), &values);
+    let gorilla = encode(gorilla::Encoder::new(), &values);
+
+    c.bench_function("decode city temps chimp", |b| {
+        b.iter(|| decode_sum(chimp::Decoder::new(InputBitStream::from_slice(&chimp))))
+    });
+    c.bench_function("decode city temps chimpn", |b| {
+        b.iter(|| decode_sum(chimpn::Decoder::new(InputBitStream::from_slice(&chimpn))))
+    });
+    c.bench_function("decode city temps gorilla", |b| {
+        b.iter(|| decode_sum(gorilla::Decoder::new(InputBitStream::from_slice(&gorilla))))
+    });
+}
+
+// the decoders that read their headers with one peek_bits instead of bit by bit
+fn header_dec(c: &mut Criterion) {
+    let values = city_temps();
+    let decimal = encode(decimal::Encoder::new(), &values);
+
+    let ints: Vec<i64> = values.iter().map(|v| (v * 10.0) as i64).collect();
+    let mut encoder: delta::Encoder<i64> = delta::Encoder::new();
+    encoder.encode_slice(&ints);
+    let delta = encoder.close().0;
+
+    // one reading a minute with a bit of jitter
+    let mut encoder = timestamp::Encoder::new();
+    for i in 0..values.len() as i64 {
+        encoder.encode(1_600_000_000 + i * 60 + i % 7);
+    }
+    let timestamps = encoder.close().0;
+
+    c.bench_function("decode city temps decimal", |b| {
+        b.iter(|| decode_sum(decimal::Decoder::new(InputBitStream::from_slice(&decimal))))
+    });
+    c.bench_function("decode city temps delta", |b| {
+        b.iter(|| {
+            let mut decoder: delta::Decoder<i64, _> =
+                delta::Decoder::new(InputBitStream::from_slice(&delta));
+            let mut sum = 0;
+            while let Ok(bits) = decoder.get_next() {
+                sum ^= bits;
+            }
+            sum
+        })
+    });
+    c.bench_function("decode timestamps", |b| {
+        b.iter_batched(
+            || timestamps.clone(),
+            |words| {
+                let mut decoder = timestamp::Decoder::new(InputBitStream::new(words));
+                let mut sum = 0;
+                while let Ok(ts) = decoder.get_next() {
+                    sum ^= ts;
+                }
+                sum
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn aligned_dec(c: &mut Criterion) {
+    let values = city_temps();
+    let words = encode(aligned::Encoder::new(), &values);
+
+    c.bench_function("decode city temps aligned", |b| {
+        b.iter(|| decode_sum(aligned::Decoder::new(InputBitStream::from_slice(&words))))
+    });
+
+    c.bench_function("decode city temps aligned batch", |b| {
+        let mut out = [0; 1024];
+        b.iter(|| {
+            let r = InputBitStream::from_slice(&words);
+            let mut decoder: aligned::Decoder<f64, _> = aligned::Decoder::new(r);
+            let mut sum = 0;
+            while let Ok(n @ 1..) = decoder.get_batch(&mut out) {
+                sum = out[..n].iter().fold(sum, |sum, &bits| sum ^ bits);
+            }
+            sum
+        })
+    });
+}
+
+fn vec_dec(c: &mut Criterion) {
+    let values = city_temps();
+    let chimp = encode(chimp::Encoder::new(), &values);
+    let aligned = encode(aligned::Encoder::new(), &values);
+
+    c.bench_function("decode city temps chimp to vec", |b| {
+        b.iter(|| {
+            let mut decoder: chimp::Decoder<f64, _> =
+                chimp::Decoder::new(InputBitStream::from_slice(&chimp));
+            decoder.decode_to_vec().unwrap()
+        })
+    });
+    c.bench_function("decode city temps aligned to vec", |b| {
+        b.iter(|| {
+            let mut decoder: aligned::Decoder<f64, _> =
+                aligned::Decoder::new(InputBitStream::from_slice(&aligned));
+            decoder.decode_to_vec().unwrap()
         })
     });
 }
@@ -25,7 +154,7 @@ criterion_group!(
     config = Criterion::default()
         .measurement_time(Duration::from_secs(30))
         .warm_up_time(Duration::from_secs(2));
-    targets = chimp_enc
+    targets = chimp_enc, bitstream_dec, header_dec, aligned_dec, vec_dec
 );
 
 criterion_main!(benches);
@@ -0,0 +1,14 @@
+#![no_main]
+
+use chimp_lib::bitstream::InputBitStream;
+use chimp_lib::decimal;
+use libfuzzer_sys::fuzz_target;
+
+// arbitrary bytes can fail to decode, but never panic
+fuzz_target!(|data: &[u8]| {
+    let mut decoder: decimal::Decoder<f64, _> = decimal::Decoder::new(InputBitStream::from_bytes(data));
+    while decoder.get_next().is_ok() {}
+
+    let mut decoder: decimal::Decoder<f32, _> = decimal::Decoder::new(InputBitStream::from_bytes(data));
+    while decoder.get_next().is_ok() {}
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use chimp_lib::bitstream::InputBitStream;
+use chimp_lib::gorilla;
+use libfuzzer_sys::fuzz_target;
+
+// arbitrary bytes can fail to decode, but never panic
+fuzz_target!(|data: &[u8]| {
+    let mut decoder: gorilla::Decoder<f64, _> = gorilla::Decoder::new(InputBitStream::from_bytes(data));
+    while decoder.get_next().is_ok() {}
+
+    let mut decoder: gorilla::Decoder<f32, _> = gorilla::Decoder::new(InputBitStream::from_bytes(data));
+    while decoder.get_next().is_ok() {}
+});
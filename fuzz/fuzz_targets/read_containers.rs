@@ -0,0 +1,23 @@
+#![no_main]
+
+use chimp_lib::block::Block;
+use chimp_lib::chunked::Chunked;
+use chimp_lib::Decode;
+use libfuzzer_sys::fuzz_target;
+
+// headers, indices and payloads all come from the input
+fuzz_target!(|data: &[u8]| {
+    if let Ok(block) = Block::read_from(data) {
+        if let Ok(mut decoder) = block.decoder::<f64>() {
+            while decoder.get_next().is_ok() {}
+        }
+        if let Ok(mut decoder) = block.decoder::<f32>() {
+            while decoder.get_next().is_ok() {}
+        }
+    }
+
+    if let Ok(chunked) = Chunked::read_from(data) {
+        let _ = chunked.decode::<f64>();
+        let _ = chunked.decode::<f32>();
+    }
+});
@@ -0,0 +1,421 @@
+import java.io.ByteArrayOutputStream;
+import java.io.IOException;
+import java.nio.ByteBuffer;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.nio.file.Paths;
+import java.util.ArrayList;
+import java.util.List;
+
+// Writes the golden streams in fixtures/ that the reference tests of chimp.rs, chimpn.rs and
+// gorilla.rs compare the encoders against
+//
+// Chimp, ChimpN and Gorilla below are the encoders of the paper authors' Java implementation
+// (https://github.com/panagiotisl/chimp), with only the bit output and what's needed to write
+// a whole stream left in. run from this directory:
+//
+//   javac -d /tmp/generate Generate.java && java -cp /tmp/generate Generate
+//
+// values.bin holds the input as big endian doubles, every other file is what the encoder of
+// the same name writes for it (chimpn_16 is ChimpN(16)), the *_empty files are for no values
+public class Generate {
+    static final long NAN = Double.doubleToRawLongBits(Double.NaN);
+
+    public static void main(String[] args) throws IOException {
+        List<Long> values = values();
+        Path out = Paths.get("fixtures");
+        Files.createDirectories(out);
+
+        ByteBuffer bytes = ByteBuffer.allocate(values.size() * 8);
+        for (long value : values) {
+            bytes.putLong(value);
+        }
+        Files.write(out.resolve("values.bin"), bytes.array());
+
+        for (boolean empty : new boolean[] {false, true}) {
+            List<Long> input = empty ? new ArrayList<>() : values;
+            String suffix = empty ? "_empty.bin" : ".bin";
+
+            Chimp chimp = new Chimp();
+            Gorilla gorilla = new Gorilla();
+            ChimpN chimp16 = new ChimpN(16);
+            ChimpN chimp128 = new ChimpN(128);
+            for (long value : input) {
+                chimp.addValue(value);
+                gorilla.addValue(value);
+                chimp16.addValue(value);
+                chimp128.addValue(value);
+            }
+            chimp.close();
+            gorilla.close();
+            chimp16.close();
+            chimp128.close();
+
+            Files.write(out.resolve("chimp" + suffix), chimp.out.toByteArray());
+            Files.write(out.resolve("gorilla" + suffix), gorilla.out.toByteArray());
+            Files.write(out.resolve("chimpn_16" + suffix), chimp16.out.toByteArray());
+            Files.write(out.resolve("chimpn_128" + suffix), chimp128.out.toByteArray());
+        }
+    }
+
+    // decimals from the datasets plus the bit patterns that hit every path of the encoders
+    static List<Long> values() throws IOException {
+        List<Long> values = new ArrayList<>();
+        String[] datasets = {
+            "../datasets/SSD_HDD_benchmarks.csv",
+            "../datasets/influxdb2-sample-data/bitcoin-price-data/bitcoin-historical-annotated.csv",
+        };
+        for (String dataset : datasets) {
+            int taken = 0;
+            for (String field : Files.readString(Paths.get(dataset)).split("[,\\s]+")) {
+                try {
+                    values.add(Double.doubleToRawLongBits(Double.parseDouble(field)));
+                    taken++;
+                } catch (NumberFormatException e) {
+                    // not a number
+                }
+                if (taken == 3000) {
+                    break;
+                }
+            }
+        }
+
+        long[] special = {
+            Double.doubleToRawLongBits(0.0),
+            Double.doubleToRawLongBits(-0.0),
+            Double.doubleToRawLongBits(Double.POSITIVE_INFINITY),
+            Double.doubleToRawLongBits(Double.NEGATIVE_INFINITY),
+            0xfff8_0000_0000_0000L, // -NaN, only the canonical NaN ends the stream
+            0x7ff0_0000_0000_beefL,
+            1L,
+            Double.doubleToRawLongBits(Double.MAX_VALUE),
+            Double.doubleToRawLongBits(Double.MIN_NORMAL),
+            // xors with 64 center bits, a lot of leading zeros and no trailing zeros
+            0x8000_0000_0000_0001L,
+            0x0000_0000_0000_0001L,
+            0x0000_0000_8000_0000L,
+            0x0000_0000_0000_0003L,
+            0xffff_ffff_ffff_ffffL,
+        };
+        for (long value : special) {
+            values.add(value);
+            values.add(value);
+        }
+
+        // cycles shorter and longer than the windows
+        for (int i = 0; i < 600; i++) {
+            values.add(Double.doubleToRawLongBits(100.0 + (i % 12) * 0.25));
+            values.add(Double.doubleToRawLongBits(Math.sin(i % 200)));
+        }
+        for (int i = 0; i < 300; i++) {
+            values.add(Double.doubleToRawLongBits(i * 1024.0));
+            values.add(Double.doubleToRawLongBits(i));
+        }
+
+        values.removeIf(value -> value == NAN);
+        return values;
+    }
+
+    // MSB first, padded with zeros to whole bytes
+    static class OutputBitStream {
+        private final ByteArrayOutputStream bytes = new ByteArrayOutputStream();
+        private int current;
+        private int free = 8;
+
+        void writeBit(boolean bit) {
+            current = current << 1 | (bit ? 1 : 0);
+            if (--free == 0) {
+                bytes.write(current);
+                current = 0;
+                free = 8;
+            }
+        }
+
+        void writeLong(long value, int len) {
+            for (int i = len - 1; i >= 0; i--) {
+                writeBit((value >>> i & 1) == 1);
+            }
+        }
+
+        void writeInt(int value, int len) {
+            writeLong(value, len);
+        }
+
+        void flush() {
+            if (free != 8) {
+                bytes.write(current << free);
+                current = 0;
+                free = 8;
+            }
+        }
+
+        byte[] toByteArray() {
+            return bytes.toByteArray();
+        }
+    }
+
+    public final static short[] leadingRepresentation = {
+        0, 0, 0, 0, 0, 0, 0, 0,
+        1, 1, 1, 1, 2, 2, 2, 2,
+        3, 3, 4, 4, 5, 5, 6, 6,
+        7, 7, 7, 7, 7, 7, 7, 7,
+        7, 7, 7, 7, 7, 7, 7, 7,
+        7, 7, 7, 7, 7, 7, 7, 7,
+        7, 7, 7, 7, 7, 7, 7, 7,
+        7, 7, 7, 7, 7, 7, 7, 7
+    };
+
+    public final static short[] leadingRound = {
+        0, 0, 0, 0, 0, 0, 0, 0,
+        8, 8, 8, 8, 12, 12, 12, 12,
+        16, 16, 18, 18, 20, 20, 22, 22,
+        24, 24, 24, 24, 24, 24, 24, 24,
+        24, 24, 24, 24, 24, 24, 24, 24,
+        24, 24, 24, 24, 24, 24, 24, 24,
+        24, 24, 24, 24, 24, 24, 24, 24,
+        24, 24, 24, 24, 24, 24, 24, 24
+    };
+
+    static class Chimp {
+        private int storedLeadingZeros = Integer.MAX_VALUE;
+        private long storedVal = 0;
+        private boolean first = true;
+        private final static int THRESHOLD = 6;
+        final OutputBitStream out = new OutputBitStream();
+
+        public void addValue(long value) {
+            if (first) {
+                writeFirst(value);
+            } else {
+                compressValue(value);
+            }
+        }
+
+        private void writeFirst(long value) {
+            first = false;
+            storedVal = value;
+            out.writeLong(storedVal, 64);
+        }
+
+        public void close() {
+            addValue(NAN);
+            out.writeBit(false);
+            out.flush();
+        }
+
+        private void compressValue(long value) {
+            long xor = storedVal ^ value;
+            if (xor == 0) {
+                // Write 0
+                out.writeBit(false);
+                out.writeBit(false);
+                storedLeadingZeros = 65;
+            } else {
+                int leadingZeros = leadingRound[Long.numberOfLeadingZeros(xor)];
+                int trailingZeros = Long.numberOfTrailingZeros(xor);
+
+                if (trailingZeros > THRESHOLD) {
+                    int significantBits = 64 - leadingZeros - trailingZeros;
+                    out.writeBit(false);
+                    out.writeBit(true);
+                    out.writeInt(leadingRepresentation[leadingZeros], 3);
+                    out.writeInt(significantBits, 6);
+                    out.writeLong(xor >>> trailingZeros, significantBits);
+                    storedLeadingZeros = 65;
+                } else if (leadingZeros == storedLeadingZeros) {
+                    out.writeBit(true);
+                    out.writeBit(false);
+                    int significantBits = 64 - leadingZeros;
+                    out.writeLong(xor, significantBits);
+                } else {
+                    storedLeadingZeros = leadingZeros;
+                    int significantBits = 64 - leadingZeros;
+                    out.writeBit(true);
+                    out.writeBit(true);
+                    out.writeInt(leadingRepresentation[leadingZeros], 3);
+                    out.writeLong(xor, significantBits);
+                }
+            }
+            storedVal = value;
+        }
+    }
+
+    static class ChimpN {
+        private int storedLeadingZeros = Integer.MAX_VALUE;
+        private long[] storedValues;
+        private boolean first = true;
+        private int previousValuesLog2;
+        private int threshold;
+        final OutputBitStream out = new OutputBitStream();
+        private int previousValues;
+        private int setLsb;
+        private int[] indices;
+        private int index = 0;
+        private int current = 0;
+        private int flagOneSize;
+        private int flagZeroSize;
+
+        public ChimpN(int previousValues) {
+            this.previousValues = previousValues;
+            this.previousValuesLog2 = (int) (Math.log(previousValues) / Math.log(2));
+            this.threshold = 6 + previousValuesLog2;
+            this.setLsb = (int) Math.pow(2, threshold + 1) - 1;
+            this.indices = new int[(int) Math.pow(2, threshold + 1)];
+            this.storedValues = new long[previousValues];
+            this.flagZeroSize = previousValuesLog2 + 2;
+            this.flagOneSize = previousValuesLog2 + 11;
+        }
+
+        public void addValue(long value) {
+            if (first) {
+                writeFirst(value);
+            } else {
+                compressValue(value);
+            }
+        }
+
+        private void writeFirst(long value) {
+            first = false;
+            storedValues[current] = value;
+            out.writeLong(storedValues[current], 64);
+            indices[(int) value & setLsb] = index;
+        }
+
+        public void close() {
+            addValue(NAN);
+            out.writeBit(false);
+            out.flush();
+        }
+
+        private void compressValue(long value) {
+            int key = (int) value & setLsb;
+            long xor;
+            int previousIndex;
+            int trailingZeros = 0;
+            int currIndex = indices[key];
+            if ((index - currIndex) < previousValues) {
+                long tempXor = value ^ storedValues[currIndex % previousValues];
+                trailingZeros = Long.numberOfTrailingZeros(tempXor);
+                if (trailingZeros > threshold) {
+                    previousIndex = currIndex % previousValues;
+                    xor = tempXor;
+                } else {
+                    previousIndex = index % previousValues;
+                    xor = storedValues[previousIndex] ^ value;
+                }
+            } else {
+                previousIndex = index % previousValues;
+                xor = storedValues[previousIndex] ^ value;
+            }
+
+            if (xor == 0) {
+                out.writeInt(previousIndex, this.flagZeroSize);
+                storedLeadingZeros = 65;
+            } else {
+                int leadingZeros = leadingRound[Long.numberOfLeadingZeros(xor)];
+
+                if (trailingZeros > threshold) {
+                    int significantBits = 64 - leadingZeros - trailingZeros;
+                    out.writeInt(512 * (previousValues + previousIndex)
+                            + 64 * leadingRepresentation[leadingZeros] + significantBits,
+                            this.flagOneSize);
+                    out.writeLong(xor >>> trailingZeros, significantBits);
+                    storedLeadingZeros = 65;
+                } else if (leadingZeros == storedLeadingZeros) {
+                    out.writeInt(2, 2);
+                    int significantBits = 64 - leadingZeros;
+                    out.writeLong(xor, significantBits);
+                } else {
+                    storedLeadingZeros = leadingZeros;
+                    int significantBits = 64 - leadingZeros;
+                    out.writeInt(24 + leadingRepresentation[leadingZeros], 5);
+                    out.writeLong(xor, significantBits);
+                }
+            }
+            current = (current + 1) % previousValues;
+            storedValues[current] = value;
+            index++;
+            indices[key] = index;
+        }
+    }
+
+    // the Compressor the reference uses for its Gorilla comparison
+    static class Gorilla {
+        private int storedLeadingZeros = Integer.MAX_VALUE;
+        private int storedTrailingZeros = 0;
+        private long storedVal = 0;
+        private boolean first = true;
+        final OutputBitStream out = new OutputBitStream();
+
+        public void addValue(long value) {
+            if (first) {
+                writeFirst(value);
+            } else {
+                compressValue(value);
+            }
+        }
+
+        private void writeFirst(long value) {
+            first = false;
+            storedVal = value;
+            out.writeLong(storedVal, 64);
+        }
+
+        public void close() {
+            addValue(NAN);
+            out.writeBit(false);
+            out.flush();
+        }
+
+        private void compressValue(long value) {
+            long xor = storedVal ^ value;
+            if (xor == 0) {
+                // Write 0
+                out.writeBit(false);
+            } else {
+                int leadingZeros = Long.numberOfLeadingZeros(xor);
+                int trailingZeros = Long.numberOfTrailingZeros(xor);
+
+                // Check overflow of leading? Can't be 32!
+                if (leadingZeros >= 32) {
+                    leadingZeros = 31;
+                }
+
+                // Store bit '1'
+                out.writeBit(true);
+
+                if (leadingZeros >= storedLeadingZeros && trailingZeros >= storedTrailingZeros) {
+                    writeExistingLeading(xor);
+                } else {
+                    writeNewLeading(xor, leadingZeros, trailingZeros);
+                }
+            }
+
+            storedVal = value;
+        }
+
+        private void writeExistingLeading(long xor) {
+            out.writeBit(false);
+            int significantBits = 64 - storedLeadingZeros - storedTrailingZeros;
+            out.writeLong(xor >>> storedTrailingZeros, significantBits);
+        }
+
+        private void writeNewLeading(long xor, int leadingZeros, int trailingZeros) {
+            out.writeBit(true);
+            // Number of leading zeros in the next 5 bits
+            out.writeInt(leadingZeros, 5);
+            int significantBits = 64 - leadingZeros - trailingZeros;
+            // Length of meaningful bits in the next 6 bits, 64 doesn't fit and is stored as 0
+            if (significantBits == 64) {
+                out.writeInt(0, 6);
+            } else {
+                out.writeInt(significantBits, 6);
+            }
+            // Store the meaningful bits of XOR
+            out.writeLong(xor >>> trailingZeros, significantBits);
+
+            storedLeadingZeros = leadingZeros;
+            storedTrailingZeros = trailingZeros;
+        }
+    }
+}
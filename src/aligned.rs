@@ -1,211 +1,560 @@
-use crate::chimpn::{LSB_MASK, THRESHOLD};
+use crate::bitstream::{SeekSource, Source, Words};
+use crate::checkpoint::{self, Checkpoint, Restart};
+use crate::chimpn::{lsb_mask, LOG_N_BITS};
+use crate::lossy::{self, Bound};
+use crate::simd::{self, Simd};
 use crate::*;
+use std::marker::PhantomData;
 // since chimp compression doesn't guarantee byte alignedness,
 // added this to have decode and encode perform better
 
 // Based off of the Patas compression implemented in DuckDB
-// need to fix close? and test some edge cases for encoding
+//
+// metadata and data bytes go into two separate arrays, so decoding is just plain array loads
+// instead of going through the bitstream. the values are written in blocks of BLOCK_LEN as
+// soon as a block is full, so neither side ever holds more than one block. a block is
+// (all big endian):
+// | log_n: 4 | value count: 28 | data bytes: 32 | metadata: u16 per value | data: u8... |
+// where both arrays are padded to whole words. a block with fewer than BLOCK_LEN values is the
+// last one, if the last one is full an empty block (just the header) ends the stream.
+// the data bytes of every value are little endian, like DuckDB does it, and the very first
+// value is xored with 0. the window is 2^log_n, so the decoder picks it up from the stream
+
+/// values per block, every block but the last has exactly this many
+pub const BLOCK_LEN: usize = 1024;
+
+// values `get_batch` gathers the data bytes of at a time
+const BATCH: usize = 64;
+
+// ring buffer of previous values, same lsb lookup as chimpn
+pub const DEFAULT_WINDOW: usize = 128;
+
+const COUNT_BITS: u32 = 32 - LOG_N_BITS;
+
+// metadata: | index of the reference: 7 | significant bytes - 1: 3 | trailing zeros: 6 |
+// in a u16, so the window can't be bigger than 128
+const MAX_LOG_N: u32 = 7;
+
+#[inline(always)]
+fn pack_metadata(index: usize, sig_bytes: u32, trail: u32) -> u16 {
+    ((index as u16) << 9) | ((sig_bytes as u16 - 1) & 7) << 6 | (trail as u16 & 0x3f)
+}
+
+// 8 significant bytes can't come with 63 trailing zeros, so that combination marks a value
+// equal to its reference (no data bytes). 1 byte with 0 trailing zeros is a real xor, e.g. 1
+const ZERO_XOR: u16 = 0x1ff;
+
+// data bytes the value takes
+#[inline(always)]
+fn sig_bytes(packed_metadata: u16) -> usize {
+    if packed_metadata & ZERO_XOR == ZERO_XOR {
+        0
+    } else {
+        ((packed_metadata as usize >> 6) & 0b111) + 1
+    }
+}
+
+fn window_log_n(n: usize) -> u32 {
+    assert!(
+        n.is_power_of_two() && (2..=1 << MAX_LOG_N).contains(&n),
+        "window has to be a power of two in [2, 128]"
+    );
+    n.trailing_zeros()
+}
+
+/// Decoder state stored in a checkpoint
+///
+/// `Checkpoint::bit` is where the block holding the value starts in the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    /// ring buffer of previous values, `curr_idx` is the latest one
+    pub stored_vals: Box<[u64]>,
+    pub curr_idx: usize,
+}
+
 #[derive(Debug)]
-pub struct Encoder {
-    first: bool,
-    pub w: OutputBitStream,
+pub struct Encoder<T: Float = f64, S: Sink = Vec<u64>> {
+    log_n: u32,
+    pub w: OutputBitStream<S>,
     curr_idx: usize,
     index: usize,
 
     stored_vals: Vec<u64>, // since Chimp128 offers close to 50% compression
     indices: Vec<usize>,
+    metadata: Vec<u16>, // of the current block
+    data: Vec<u8>,
+    count: u64,
+    checkpoint_every: u64,
+    checkpoints: Vec<Checkpoint<State>>,
+    lossy: Option<Bound>,
+    float: PhantomData<T>,
 }
 
-impl Encoder {
+impl<T: Float> Encoder<T> {
     pub fn new() -> Self {
+        Encoder::with_sink(Vec::new())
+    }
+
+    /// room for `values` values in the sink, about a word each. only a block is kept in memory
+    pub fn with_capacity(values: usize) -> Self {
+        Encoder::with_sink(Vec::with_capacity(values))
+    }
+
+    /// searches the last `n` values for a reference, `n` is a power of two in [2, 128]
+    pub fn with_window(n: usize) -> Self {
+        Encoder::with_sink_and_window(Vec::new(), n)
+    }
+}
+
+impl<T: Float> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    ///
+    /// every block reaches the sink as soon as it's full, see `BLOCK_LEN`
+    pub fn with_sink(sink: S) -> Self {
+        Encoder::with_sink_and_window(sink, DEFAULT_WINDOW)
+    }
+
+    pub fn with_sink_and_window(sink: S, n: usize) -> Self {
+        let log_n = window_log_n(n);
         Encoder {
-            first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![usize::MAX; 2_usize.pow(14)],
+            log_n,
+            stored_vals: vec![0; n],
+            indices: vec![usize::MAX; lsb_mask::<T>(log_n) as usize + 1],
             curr_idx: 0,
             index: 0,
-            w: OutputBitStream::new(),
+            w: OutputBitStream::with_sink(sink),
+            metadata: Vec::with_capacity(BLOCK_LEN),
+            data: Vec::with_capacity(BLOCK_LEN * T::BITS as usize / 8),
+            count: 0,
+            checkpoint_every: 0,
+            checkpoints: Vec::new(),
+            lossy: None,
+            float: PhantomData,
         }
     }
 
-    pub fn with_capacity(capa: usize) -> Self {
-         Encoder {
-            first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![usize::MAX; 2_usize.pow(14)],
-            curr_idx: 0,
-            index: 0,
-            w: OutputBitStream::with_capacity(capa),
-        }
+    /// records a checkpoint every `every` values from now on, 0 turns them off
+    pub fn checkpoint_every(&mut self, every: u64) {
+        self.checkpoint_every = every;
     }
 
-    fn insert_first(&mut self, value: f64) {
-        self.stored_vals[self.index] = value.to_bits();
-        self.indices[(value.to_bits() & LSB_MASK) as usize] = self.index;
+    /// rounds every value encoded from now on as far as `bound` allows, see `lossy::round`
+    pub fn lossy(&mut self, bound: Bound) {
+        self.lossy = Some(bound);
+    }
 
-        self.w.write_bits(value.to_bits(), 64);
+    pub fn checkpoints(&self) -> &[Checkpoint<State>] {
+        &self.checkpoints
+    }
+
+    fn state(&self) -> State {
+        State {
+            stored_vals: self.stored_vals.clone().into_boxed_slice(),
+            curr_idx: self.curr_idx,
+        }
     }
 
     #[inline(always)]
-    fn insert_value(&mut self, value: f64) {
-        let mut lsb_index = self.indices[(value.to_bits() & LSB_MASK) as usize];
+    fn insert_value(&mut self, value: T) {
+        let value = value.to_bits64();
+        let window = self.stored_vals.len();
+        let mut lsb_index = self.indices[(value & lsb_mask::<T>(self.log_n)) as usize];
 
         // is not in ring buffer --> take previous
-        if self.index < lsb_index || (self.index - lsb_index) >= 128 {
+        if self.index < lsb_index || (self.index - lsb_index) >= window {
             lsb_index = self.index;
         }
 
-        lsb_index %= 128;
+        lsb_index &= window - 1;
         let ref_value = self.stored_vals[lsb_index];
 
-        let xor = ref_value ^ value.to_bits();
+        // leading zeros of the whole u64 so the significant bits also work out for f32
+        let xor = ref_value ^ value;
         let trail = xor.trailing_zeros();
         let lead = xor.leading_zeros();
 
-        let sig_bits = if xor == 0 { 1 } else { 64 - trail - lead };
-        let sig_bytes = (sig_bits >> 3) + if sig_bits & 7 != 0 { 1 } else { 0 };
-
-        let packed_metadata = (lsb_index as u32) << 9 | ((sig_bytes - 1) & 7) << 6 | (trail & 0x3f);
-        self.w.write_bits(packed_metadata as u64, 16);
-
-        if xor != 0 {
-            self.w.write_bits(xor >> trail, sig_bytes * 8);
+        if xor == 0 {
+            self.metadata.push((lsb_index as u16) << 9 | ZERO_XOR);
+        } else {
+            let sig_bytes = (64 - trail - lead).div_ceil(8);
+            self.metadata.push(pack_metadata(lsb_index, sig_bytes, trail));
+            self.data
+                .extend_from_slice(&(xor >> trail).to_le_bytes()[..sig_bytes as usize]);
         }
 
         self.curr_idx += 1;
-        self.curr_idx %= 128;
+        self.curr_idx &= window - 1;
 
-        self.stored_vals[self.curr_idx] = value.to_bits();
+        self.stored_vals[self.curr_idx] = value;
 
         self.index += 1;
-        self.indices[(value.to_bits() & LSB_MASK) as usize] = self.index;
+        self.indices[(value & lsb_mask::<T>(self.log_n)) as usize] = self.index;
+    }
 
+    // writes out the current block, even an empty one
+    fn flush_block(&mut self) {
+        self.w.write_bits(self.log_n as u64, LOG_N_BITS);
+        self.w.write_bits(self.metadata.len() as u64, COUNT_BITS);
+        self.w.write_bits(self.data.len() as u64, 32);
+        for chunk in self.metadata.chunks(4) {
+            let mut word = 0;
+            for (i, &m) in chunk.iter().enumerate() {
+                word |= (m as u64) << (48 - 16 * i);
+            }
+            self.w.write_bits(word, 64);
+        }
+        for chunk in self.data.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.w.write_bits(u64::from_be_bytes(word), 64);
+        }
+        self.metadata.clear();
+        self.data.clear();
     }
 }
 
-impl Encode for Encoder {
-    fn encode_vec(values: &Vec<f64>) -> Self {
-        let mut patas = Encoder {
-            first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![0; 2_usize.pow(14)],
-            curr_idx: 0,
-            index: 0,
-            w: OutputBitStream::with_capacity(values.len() / 2),
+impl<T: Float, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
+
+    #[inline(always)]
+    fn encode(&mut self, value: T) {
+        let value = match self.lossy {
+            Some(bound) => lossy::round(value, bound),
+            None => value,
         };
-        for &val in values {
-            patas.encode(val);
+        self.insert_value(value);
+        if self.metadata.len() == BLOCK_LEN {
+            self.flush_block();
         }
-        patas
-    }
 
-    #[inline(always)]
-    fn encode(&mut self, value: f64) {
-        if self.first {
-            self.first = false;
-            self.insert_first(value);
-        } else {
-            self.insert_value(value);
+        self.count += 1;
+        if checkpoint::due(self.checkpoint_every, self.count) {
+            // blocks are whole words, so the next one starts right where the sink is
+            self.checkpoints.push(Checkpoint {
+                index: self.count,
+                bit: self.w.bits_written(),
+                state: self.state(),
+            });
         }
     }
 
-    fn close(self) -> (Box<[u64]>, u64) {
+    fn close(self) -> (S::Output, u64) {
         let mut this = self;
-        this.w.write_bits(0xffff, 16);
-        this.w.write_bit(0); // not sure why actual implementation does this
-        let buffer = this.w.close();
-        let len = &buffer.len() * 64;
-        (buffer, len as u64)
+        // empty stream stays empty
+        if this.count > 0 {
+            this.flush_block();
+        }
+        this.w.close()
     }
 }
 
-pub struct Decoder {
-    first: bool,
-    done: bool,
+pub struct Decoder<T: Float = f64, S: Source = Words> {
+    metadata: Vec<u16>, // of the current block
+    data: Vec<u8>,      // padded with 8 zero bytes so every read can load a whole u64
+    data_len: usize,
+    data_pos: usize,
+    next: usize, // index of the next value in the block
+    last: bool,  // the current block is the last one
 
-    stored_vals: Vec<u64>,
-    curr: u64, // curr stored value
+    stored_vals: Vec<u64>, // empty until the first block says how big the window is
+    curr: u64,             // curr stored value
     curr_idx: usize,
-    pub r: InputBitStream,
+    pub r: InputBitStream<S>,
+    position: u64, // number of values decoded
+    checkpoints: Vec<Checkpoint<State>>,
+    float: PhantomData<T>,
 }
 
-impl Decoder {
-    pub fn new(r: InputBitStream) -> Self {
+impl<T: Float, S: Source> Decoder<T, S> {
+    /// the window is read from the stream
+    pub fn new(r: InputBitStream<S>) -> Self {
         Decoder {
-            first: true,
-            done: false,
-            stored_vals: (0..128).collect(),
+            metadata: Vec::new(),
+            data: Vec::new(),
+            data_len: 0,
+            data_pos: 0,
+            next: 0,
+            last: false,
+            stored_vals: Vec::new(),
             curr: 0,
             curr_idx: 0,
             r,
+            position: 0,
+            checkpoints: Vec::new(),
+            float: PhantomData,
         }
     }
 
-    fn get_first(&mut self) -> Result<(), Error> {
-        self.curr = self.r.read_bits(64)?;
-        self.stored_vals[self.curr_idx] = self.curr;
+    /// checkpoints recorded by the encoder, used by `seek`
+    pub fn set_checkpoints(&mut self, checkpoints: Vec<Checkpoint<State>>) {
+        self.checkpoints = checkpoints;
+    }
+
+    // reads the next block into memory, EOF after the last one
+    fn load_block(&mut self) -> Result<(), Error> {
+        self.metadata.clear();
+        self.data_len = 0;
+        self.data_pos = 0;
+        self.next = 0;
+        let res = self.read_block();
+        if res.is_err() {
+            // don't hand out the rest of a broken block on the next call
+            self.metadata.clear();
+        }
+        res
+    }
+
+    fn read_block(&mut self) -> Result<(), Error> {
+        // nothing was encoded
+        if self.last || self.position == 0 && self.r.is_empty()? {
+            self.last = true;
+            return Err(Error::EOF);
+        }
+
+        let header = self.r.read_bits(64)?;
+        let log_n = match (header >> 60) as u32 {
+            log_n @ 1..=MAX_LOG_N => log_n,
+            _ => return Err(Error::InvalidCode),
+        };
+        let count = (header >> 32) as usize & ((1 << COUNT_BITS) - 1);
+        let data_len = header as u32 as usize;
+        if self.stored_vals.is_empty() {
+            self.stored_vals = vec![0; 1 << log_n];
+        }
+        // every block has the same window and at most 8 data bytes per value
+        if self.stored_vals.len() != 1 << log_n || count > BLOCK_LEN || data_len > 8 * count {
+            return Err(Error::InvalidCode);
+        }
+        if count == 0 {
+            // only ends a stream whose last block was full
+            if self.position == 0 {
+                return Err(Error::InvalidCode);
+            }
+            self.last = true;
+            return Err(Error::EOF);
+        }
+        self.last = count < BLOCK_LEN;
+
+        for _ in 0..count.div_ceil(4) {
+            let word = self.r.read_bits(64)?;
+            self.metadata
+                .extend([48, 32, 16, 0].map(|shift| (word >> shift) as u16));
+        }
+        self.metadata.truncate(count);
+
+        self.data.clear();
+        for _ in 0..data_len.div_ceil(8) {
+            self.data
+                .extend_from_slice(&self.r.read_bits(64)?.to_be_bytes());
+        }
+        self.data.truncate(data_len);
+        self.data.extend_from_slice(&[0; 8]);
+        self.data_len = data_len;
         Ok(())
     }
 
-    #[allow(unused)]
+    // the next `len` data bytes, little endian
+    #[inline(always)]
+    fn read_bytes(&mut self, len: usize) -> Result<u64, Error> {
+        if self.data_pos + len > self.data_len {
+            return Err(Error::InvalidCode);
+        }
+        let word = &self.data[self.data_pos..self.data_pos + 8];
+        let bytes = u64::from_le_bytes(word.try_into().unwrap());
+        self.data_pos += len;
+        Ok(bytes & (u64::MAX >> (64 - 8 * len)))
+    }
+
+    // the value at `next` in the current block, there has to be one
+    #[inline(always)]
     fn get_value(&mut self) -> Result<(), Error> {
-        let packed_metadata = self.r.read_bits(16)?;
+        let packed_metadata = self.metadata[self.next];
 
         let lsb_index = packed_metadata as usize >> 9;
-        let sig_bytes = ((packed_metadata as u32 >> 6) & 0b111) + 1;
         let trail = packed_metadata & 0x3f;
 
-        if packed_metadata == 0xffff {
-            self.curr = NAN;
-            return Ok(());
-        }
-
-        if sig_bytes == 1 && trail == 0 {
-            self.curr = self.stored_vals[lsb_index];
-        } else {
-            let ref_value = self.stored_vals[lsb_index];
-            let xor = self.r.read_bits(sig_bytes * 8)? << trail;
-            self.curr = ref_value ^ xor;
+        let ref_value = self.stored_vals[lsb_index & (self.stored_vals.len() - 1)];
+        match sig_bytes(packed_metadata) {
+            0 => self.curr = ref_value,
+            sig_bytes => self.curr = ref_value ^ (self.read_bytes(sig_bytes)? << trail),
         }
 
         self.curr_idx += 1;
-        self.curr_idx %= 128;
+        self.curr_idx &= self.stored_vals.len() - 1;
         self.stored_vals[self.curr_idx] = self.curr;
 
+        self.next += 1;
+        self.position += 1;
         Ok(())
     }
 
+    /// decodes up to `out.len()` values into `out` (as bits, like `get_next`) and returns how
+    /// many, fewer only at the end of the stream. the data bytes of many values are gathered at
+    /// once with the widest simd registers the cpu has. an invalid value returns the error,
+    /// everything before it is decoded like with `get_next`
+    pub fn get_batch(&mut self, out: &mut [u64]) -> Result<usize, Error> {
+        self.get_batch_with(out, Simd::detect())
+    }
+
+    /// `get_batch` with the given instruction set, panics if the cpu doesn't have it
+    pub fn get_batch_with(&mut self, out: &mut [u64], simd: Simd) -> Result<usize, Error> {
+        let mut n = 0;
+        let mut offsets = [0; BATCH];
+        let mut shifts = [0; BATCH];
+        let mut trails = [0; BATCH];
+        let mut xors = [0; BATCH];
+        while n < out.len() {
+            if self.next == self.metadata.len() {
+                match self.load_block() {
+                    Ok(()) => {}
+                    Err(Error::EOF) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            let start = self.next;
+            let len = (out.len() - n).min(BATCH).min(self.metadata.len() - start);
+            let metadata = &self.metadata[start..start + len];
+
+            let mut pos = self.data_pos as u64;
+            for (i, &packed_metadata) in metadata.iter().enumerate() {
+                let sig_bytes = sig_bytes(packed_metadata) as u64;
+                offsets[i] = pos;
+                shifts[i] = 64 - 8 * sig_bytes;
+                trails[i] = packed_metadata as u64 & 0x3f;
+                pos += sig_bytes;
+            }
+
+            let out = &mut out[n..n + len];
+            if pos > self.data_len as u64 {
+                // one of them runs past the data, get_value returns the error right there
+                for val in out {
+                    self.get_value()?;
+                    *val = self.curr;
+                }
+                n += len;
+                continue;
+            }
+            let (offsets, shifts, trails) = (&offsets[..len], &shifts[..len], &trails[..len]);
+            let xors = &mut xors[..len];
+            simd::gather_xors(simd, &self.data, offsets, shifts, trails, xors);
+
+            // every value needs the one before it in the ring buffer, that part stays scalar
+            let mask = self.stored_vals.len() - 1;
+            for ((&packed_metadata, &xor), val) in metadata.iter().zip(&*xors).zip(out) {
+                self.curr = self.stored_vals[(packed_metadata as usize >> 9) & mask] ^ xor;
+                self.curr_idx = (self.curr_idx + 1) & mask;
+                self.stored_vals[self.curr_idx] = self.curr;
+                *val = self.curr;
+            }
+            self.data_pos = pos as usize;
+            self.next += len;
+            self.position += len as u64;
+            n += len;
+        }
+        Ok(n)
+    }
+
     fn get_next(&mut self) -> Result<u64, Error> {
-        if self.done {
-            return Err(Error::EOF);
+        if self.next == self.metadata.len() {
+            self.load_block()?;
         }
+        self.get_value()?;
+        Ok(self.curr)
+    }
+}
 
-        if self.first {
-            self.get_first()?;
-            self.first = false;
-        } else {
-            self.get_value()?;
+impl<T: Float, S: SeekSource> Seek<T> for Decoder<T, S> {
+    fn seek(&mut self, n: u64) -> Result<(), Error> {
+        match checkpoint::restart(&self.checkpoints, self.position, n) {
+            Restart::Continue => {}
+            Restart::Start => {
+                self.r.seek(0)?;
+                self.metadata.clear();
+                self.next = 0;
+                self.last = false;
+                self.stored_vals.clear();
+                self.curr_idx = 0;
+                self.position = 0;
+            }
+            Restart::At(cp) => {
+                let window = cp.state.stored_vals.len();
+                if !window.is_power_of_two() {
+                    return Err(Error::InvalidCode);
+                }
+                self.r.seek(cp.bit)?;
+                self.metadata.clear();
+                self.next = 0;
+                self.last = false;
+                self.stored_vals = cp.state.stored_vals.to_vec();
+                self.curr_idx = cp.state.curr_idx % window;
+                self.curr = self.stored_vals[self.curr_idx];
+                self.position = cp.index;
+
+                // skip the values of the block before the checkpoint, only their data bytes
+                // are needed for that
+                let skip = (cp.index % BLOCK_LEN as u64) as usize;
+                if skip > 0 {
+                    self.load_block()?;
+                    if skip > self.metadata.len() {
+                        return Err(Error::InvalidCode);
+                    }
+                    self.data_pos = self.metadata[..skip].iter().map(|&m| sig_bytes(m)).sum();
+                    self.next = skip;
+                }
+            }
         }
 
-        if self.curr == NAN {
-            Err(Error::EOF)
-        } else {
-            Ok(self.curr)
+        while self.position < n {
+            self.get_next()?;
         }
+        Ok(())
     }
 }
 
-impl Decode for Decoder {
+impl<T: Float, S: Source> Decode<T> for Decoder<T, S> {
     fn get_next(&mut self) -> Result<u64, Error> {
         self.get_next()
     }
+
+    /// goes through `get_batch`
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let mut bits = [0; BATCH];
+        let mut n = 0;
+        for chunk in out.chunks_mut(BATCH) {
+            let decoded = self.get_batch(&mut bits[..chunk.len()])?;
+            for (val, &bits) in chunk.iter_mut().zip(&bits[..decoded]) {
+                *val = T::from_bits64(bits);
+            }
+            n += decoded;
+            if decoded < chunk.len() {
+                break;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Decoder, Encoder};
-    use crate::bitstream::InputBitStream;
-    use crate::Encode;
+    use super::{Decoder, Encoder, BLOCK_LEN};
+    use crate::bitstream::{Error, InputBitStream, WriteSink};
+    use crate::simd::Simd;
+    use crate::{Decode, Encode, Seek};
 
     #[test]
     fn simple_test() {
@@ -223,7 +572,7 @@ mod test {
         // 0000000|110|000000 || 110101010101010101010101010101010101010101010101
 
         let (bytes, _) = encoder.close();
-        let mut decoder = Decoder::new(InputBitStream::new(bytes));
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
         let mut datapoints = Vec::new();
 
         while let Ok(val) = decoder.get_next() {
@@ -232,4 +581,294 @@ mod test {
 
         assert_eq!(datapoints, float_vec);
     }
+
+    #[test]
+    fn batch_test() {
+        // repeats, values equal to their reference and every number of data bytes
+        let float_vec: Vec<f64> = (0..3000)
+            .map(|i| match i % 7 {
+                0 => 1.0,
+                1 => (i as f64).sqrt(),
+                2 => ((i % 300) as f64 * 1.7).sin(),
+                _ => (i / 10) as f64 * 0.5,
+            })
+            .collect();
+        let expected: Vec<u64> = float_vec.iter().map(|val| val.to_bits()).collect();
+        let mut encoder = Encoder::<f64>::new();
+        encoder.encode_slice(&float_vec);
+        let (bytes, _) = encoder.close();
+
+        for simd in Simd::ALL.into_iter().filter(|simd| simd.is_available()) {
+            for batch in [1, 3, 64, 100, 5000] {
+                let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes.clone()));
+                let mut out = vec![0; batch];
+                let mut decoded = Vec::new();
+                loop {
+                    let n = decoder.get_batch_with(&mut out, simd).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    decoded.extend_from_slice(&out[..n]);
+                }
+                assert_eq!(decoded, expected, "{simd:?} {batch}");
+            }
+
+            // picks up wherever get_next and seek left off
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes.clone()));
+            let mut out = [0; 10];
+            decoder.get_next().unwrap();
+            assert_eq!(decoder.get_batch_with(&mut out, simd), Ok(10));
+            assert_eq!(out[..], expected[1..11]);
+            decoder.seek(2995).unwrap();
+            assert_eq!(decoder.get_batch_with(&mut out, simd), Ok(5));
+            assert_eq!(out[..5], expected[2995..]);
+            assert_eq!(decoder.get_batch_with(&mut out, simd), Ok(0));
+            assert_eq!(decoder.get_next(), Err(Error::EOF));
+
+            // fewer data bytes in the first block than its metadata needs
+            let mut words = bytes.to_vec();
+            words[0] -= (words[0] & 0xffff_ffff) / 2;
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(words.into()));
+            let mut out = vec![0; 3000];
+            assert_eq!(decoder.get_batch_with(&mut out, simd), Err(Error::InvalidCode));
+            let n = decoder.position as usize;
+            assert!(n > 0 && n < 3000);
+            assert_eq!(out[..n], expected[..n]);
+        }
+
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(Box::new([])));
+        assert_eq!(decoder.get_batch(&mut [0; 4]), Ok(0));
+    }
+
+    #[test]
+    fn window_test() {
+        // noisy pattern repeating every 100 values
+        let float_vec: Vec<f64> = (0..2000)
+            .map(|i| ((i % 100) as f64 * 1.7).sin() * 1000.0 + 2000.0)
+            .collect();
+
+        let mut sizes = Vec::new();
+        for n in [2, 16, 64, 128] {
+            let mut encoder = Encoder::with_window(n);
+            for &val in &float_vec {
+                encoder.encode(val);
+            }
+
+            let (bytes, len) = encoder.close();
+            sizes.push(len);
+            assert_eq!(bytes[0] >> 60, n.trailing_zeros() as u64);
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
+            assert_eq!(decoder.decode_to_vec(), Ok(float_vec.clone()));
+        }
+        // only a window bigger than the period finds the repeats
+        assert!(sizes[3] < sizes[2]);
+
+        // without any data bytes it's all header: a u16 of metadata per value, whatever the window
+        let float_vec = vec![0.0; 1000];
+        for n in [2, 128] {
+            let mut encoder = Encoder::<f64>::with_window(n);
+            encoder.encode_slice(&float_vec);
+            let (bytes, len) = encoder.close();
+            assert_eq!(len, (1 + 1000 / 4) * 64);
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
+            assert_eq!(decoder.decode_to_vec(), Ok(float_vec.clone()));
+        }
+
+        let float_vec = vec![1.5f32, 2.5, 1.5, 2.5, 3.0];
+        let mut encoder = Encoder::<f32>::with_window(2);
+        for &val in &float_vec {
+            encoder.encode(val);
+        }
+        let (bytes, _) = encoder.close();
+        let mut decoder: Decoder<f32> = Decoder::new(InputBitStream::new(bytes.clone()));
+        assert_eq!(decoder.decode_to_vec(), Ok(float_vec));
+
+        // windows that can't be stored
+        for log_n in [0, 8, 15] {
+            let mut corrupt = bytes.to_vec();
+            corrupt[0] = corrupt[0] & (u64::MAX >> 4) | log_n << 60;
+            let mut decoder: Decoder<f32> = Decoder::new(InputBitStream::new(corrupt.into()));
+            assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+        }
+    }
+
+    // compares bits, the shapes include NaNs
+    fn round_trip<T: crate::Float>(bits: &[u64]) {
+        let values: Vec<T> = bits.iter().map(|&v| T::from_bits64(v)).collect();
+        let mut encoder = Encoder::<T>::new();
+        encoder.encode_slice(&values);
+        let (words, _) = encoder.close();
+        let mut decoder: Decoder<T> = Decoder::new(InputBitStream::new(words));
+        for &expected in bits {
+            assert_eq!(decoder.get_next(), Ok(expected));
+        }
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    fn xor_shape_test() {
+        // every xor with its lowest set bit at `trail` and `width` significant bits,
+        // against the previous value, so both 1 byte + 0 trailing zeros and full 64 bit xors
+        for trail in 0..64 {
+            for width in 1..=64 - trail {
+                let xor = (1u64 << (width - 1) | 1) << trail;
+                for base in [0u64, 0x4045_3333_3333_3333, u64::MAX] {
+                    round_trip::<f64>(&[base, base ^ xor, base ^ xor, base]);
+                }
+            }
+        }
+
+        for trail in 0..32 {
+            for width in 1..=32 - trail {
+                let xor = (1u64 << (width - 1) | 1) << trail;
+                round_trip::<f32>(&[0x4228_0000, 0x4228_0000 ^ xor, 0x4228_0000]);
+            }
+        }
+
+        // 1 significant bit at either end of the word
+        round_trip::<f64>(&[0, 1 << 63, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn metadata_collision_test() {
+        // values with distinct lsbs, so the repeat is found at the last index of the window
+        let mut values: Vec<f64> = (0..128)
+            .map(|i| f64::from_bits(0x4059_0000_0000_0000 + i))
+            .collect();
+        values.push(values[126]);
+        values.extend([1.5, 2.5, 1.5]);
+
+        let mut encoder = Encoder::new();
+        for &val in &values {
+            encoder.encode(val);
+        }
+        // used to be the end of stream marker
+        assert_eq!(encoder.metadata[128], 0xffff);
+
+        let (words, _) = encoder.close();
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(words));
+        assert_eq!(decoder.decode_to_vec(), Ok(values));
+    }
+
+    #[test]
+    fn layout_test() {
+        let float_vec = vec![1.0f64, 1.0, 3.0, 1.0];
+        let mut encoder = Encoder::<f64>::new();
+        encoder.encode_slice(&float_vec);
+        let (words, len) = encoder.close();
+
+        // window, count and data bytes, one word of metadata, 2 + 0 + 2 + 2 data bytes
+        assert_eq!(len, 3 * 64);
+        assert_eq!(words[0], 7 << 60 | 4 << 32 | 6);
+        assert_eq!(words[1], 0x0074_03ff_0473_0673);
+        assert_eq!(words[2], 0xff03_ff0f_ff0f_0000);
+
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(words.clone()));
+        assert_eq!(decoder.decode_to_vec(), Ok(float_vec));
+
+        // data bytes cut off
+        let mut decoder: Decoder<f64, _> = Decoder::new(InputBitStream::from_slice(&words[..2]));
+        assert_eq!(decoder.get_next(), Err(Error::Truncated));
+
+        // metadata pointing past the data bytes
+        let mut corrupt = words.to_vec();
+        corrupt[0] -= 2;
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(corrupt.into()));
+        assert_eq!(decoder.get_next(), Ok(1f64.to_bits()));
+        assert_eq!(decoder.get_next(), Ok(1f64.to_bits()));
+        assert_eq!(decoder.get_next(), Ok(3f64.to_bits()));
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+
+        // values but no data bytes at all is corrupt, not an empty stream
+        let mut corrupt = words.to_vec();
+        corrupt[0] -= 6;
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(corrupt.into()));
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+
+        // more values than a block holds, more data bytes than the values can have
+        for header in [7 << 60 | 1025 << 32 | 6, 7 << 60 | 4 << 32 | 33] {
+            let mut corrupt = words.to_vec();
+            corrupt[0] = header;
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(corrupt.into()));
+            assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+        }
+    }
+
+    #[test]
+    fn block_test() {
+        let float_vec: Vec<f64> = (0..2 * BLOCK_LEN + 1)
+            .map(|i| (i / 3) as f64 * 0.25)
+            .collect();
+
+        // a block reaches the sink as soon as it's full
+        let mut encoder = Encoder::with_sink(WriteSink::new(Vec::new()));
+        encoder.encode_slice(&float_vec[..BLOCK_LEN - 1]);
+        assert_eq!(encoder.w.bits_written(), 0);
+        encoder.encode(float_vec[BLOCK_LEN - 1]);
+        let block = encoder.w.bits_written() as usize / 8;
+        assert!(block > 0);
+        encoder.encode_slice(&float_vec[BLOCK_LEN..]);
+        let (bytes, _) = encoder.close();
+        let bytes = bytes.unwrap();
+
+        // and the decoder only needs that block for its values
+        let r = InputBitStream::from_reader(&bytes[..block]);
+        let mut decoder: Decoder<f64, _> = Decoder::new(r);
+        for &val in &float_vec[..BLOCK_LEN] {
+            assert_eq!(decoder.get_next(), Ok(val.to_bits()));
+        }
+        assert_eq!(decoder.get_next(), Err(Error::Truncated));
+
+        for len in [1, BLOCK_LEN - 1, BLOCK_LEN, 2 * BLOCK_LEN, 2 * BLOCK_LEN + 1] {
+            let mut encoder = Encoder::new();
+            encoder.encode_slice(&float_vec[..len]);
+            let (words, _) = encoder.close();
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(words.clone()));
+            assert_eq!(decoder.decode_to_vec(), Ok(float_vec[..len].to_vec()));
+
+            // full blocks are followed by an empty one, a stream without it is cut off
+            if len % BLOCK_LEN == 0 {
+                assert_eq!(words.last(), Some(&(7 << 60)));
+                let words = &words[..words.len() - 1];
+                let mut decoder: Decoder<f64, _> =
+                    Decoder::new(InputBitStream::from_slice(words));
+                assert_eq!(decoder.decode_to_vec(), Err(Error::Truncated));
+            }
+        }
+
+        // checkpoints in the middle of a block and right at the start of one
+        for len in [2 * BLOCK_LEN, 2 * BLOCK_LEN + 1] {
+            let mut encoder = Encoder::new();
+            encoder.checkpoint_every(64);
+            encoder.encode_slice(&float_vec[..len]);
+            let checkpoints = encoder.checkpoints().to_vec();
+            let (words, _) = encoder.close();
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(words));
+            decoder.set_checkpoints(checkpoints);
+            for (a, b) in [(1100, 1110), (1024, 1030), (10, 2000), (2040, 2048), (5, 6)] {
+                let expected = float_vec[a as usize..b as usize].to_vec();
+                assert_eq!(decoder.decode_range(a..b), Ok(expected));
+            }
+            assert_eq!(decoder.seek(len as u64), Ok(()));
+            assert_eq!(decoder.get_next(), Err(Error::EOF));
+            assert_eq!(decoder.seek(len as u64 + 1), Err(Error::EOF));
+        }
+
+        // an empty block can't be the whole stream
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(Box::new([7 << 60])));
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_window_test() {
+        Encoder::<f64>::with_window(3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn window_too_big_test() {
+        // the index wouldn't fit in the u16 metadata anymore
+        Encoder::<f64>::with_window(256);
+    }
 }
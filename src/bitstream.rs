@@ -1,18 +1,27 @@
 use crate::Bit;
+use std::io::{self, Read, Write};
 use std::{error, fmt};
 
 /// Error
 ///
 /// Enum used to represent potential errors when interacting with a stream.
-#[derive(Debug, PartialEq)]
+/// `EOF` is only returned once the end of the stream is reached as intended,
+/// `Truncated` means the input ended before that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     EOF,
+    Truncated,
+    InvalidCode,
+    Io(io::ErrorKind),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             Error::EOF => write!(f, "Encountered the end of the stream"),
+            Error::Truncated => write!(f, "Input ended before the end of the stream"),
+            Error::InvalidCode => write!(f, "Encountered an invalid code in the stream"),
+            Error::Io(kind) => write!(f, "Failed to read from the source: {}", kind),
         }
     }
 }
@@ -21,29 +30,250 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::EOF => "Encountered the end of the stream",
+            Error::Truncated => "Input ended before the end of the stream",
+            Error::InvalidCode => "Encountered an invalid code in the stream",
+            Error::Io(_) => "Failed to read from the source",
+        }
+    }
+}
+
+/// Where an `OutputBitStream` puts its completed 64-bit words
+pub trait Sink {
+    type Output;
+
+    fn push(&mut self, word: u64);
+    fn finish(self) -> Self::Output;
+}
+
+impl Sink for Vec<u64> {
+    type Output = Box<[u64]>;
+
+    #[inline(always)]
+    fn push(&mut self, word: u64) {
+        Vec::push(self, word);
+    }
+
+    fn finish(self) -> Box<[u64]> {
+        self.into_boxed_slice()
+    }
+}
+
+/// Writes every completed word as 8 big endian bytes
+///
+/// Writes happen word by word, so wrap unbuffered writers (files, sockets) in a `BufWriter`.
+/// The first io error stops all further writes and is returned by `finish`.
+#[derive(Debug)]
+pub struct WriteSink<W: Write> {
+    w: W,
+    res: io::Result<()>,
+}
+
+impl<W: Write> WriteSink<W> {
+    pub fn new(w: W) -> Self {
+        WriteSink { w, res: Ok(()) }
+    }
+}
+
+impl<W: Write> Sink for WriteSink<W> {
+    /// the writer, after everything was flushed
+    type Output = io::Result<W>;
+
+    #[inline(always)]
+    fn push(&mut self, word: u64) {
+        if self.res.is_ok() {
+            self.res = self.w.write_all(&word.to_be_bytes());
+        }
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.res?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// Where an `InputBitStream` gets its 64-bit words from
+pub trait Source {
+    /// `Ok(None)` once the input is exhausted
+    fn next_word(&mut self) -> Result<Option<u64>, Error>;
+}
+
+/// Sources that can jump to any word, needed for seeking
+pub trait SeekSource: Source {
+    fn seek_word(&mut self, index: u64) -> Result<(), Error>;
+}
+
+/// In-memory words, owned (`Box<[u64]>`) or borrowed (`&[u64]`, e.g. a memory-mapped file)
+#[derive(Debug)]
+pub struct Words<B: AsRef<[u64]> = Box<[u64]>> {
+    buffer: B,
+    index: usize,
+}
+
+impl<B: AsRef<[u64]>> Words<B> {
+    pub fn new(buffer: B) -> Self {
+        Words { buffer, index: 0 }
+    }
+}
+
+impl<B: AsRef<[u64]>> Source for Words<B> {
+    #[inline(always)]
+    fn next_word(&mut self) -> Result<Option<u64>, Error> {
+        let word = self.buffer.as_ref().get(self.index).copied();
+        self.index += word.is_some() as usize;
+        Ok(word)
+    }
+}
+
+impl<B: AsRef<[u64]>> SeekSource for Words<B> {
+    fn seek_word(&mut self, index: u64) -> Result<(), Error> {
+        if index > self.buffer.as_ref().len() as u64 {
+            return Err(Error::Truncated);
+        }
+        self.index = index as usize;
+        Ok(())
+    }
+}
+
+/// In-memory bytes in the same big endian layout `WriteSink` produces, no alignment required
+#[derive(Debug)]
+pub struct Bytes<B: AsRef<[u8]>> {
+    buffer: B,
+    index: usize,
+    padded: bool,
+}
+
+impl<B: AsRef<[u8]>> Bytes<B> {
+    pub fn new(buffer: B) -> Self {
+        Bytes {
+            buffer,
+            index: 0,
+            padded: false,
+        }
+    }
+
+    /// for streams that are only padded to whole bytes, like the reference implementation's,
+    /// the last partial word is filled up with zeros instead of being truncated
+    pub fn padded(buffer: B) -> Self {
+        Bytes {
+            buffer,
+            index: 0,
+            padded: true,
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Source for Bytes<B> {
+    #[inline(always)]
+    fn next_word(&mut self) -> Result<Option<u64>, Error> {
+        let rest = &self.buffer.as_ref()[self.index..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if rest.len() < 8 {
+            if !self.padded {
+                return Err(Error::Truncated);
+            }
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            self.index += rest.len();
+            return Ok(Some(u64::from_be_bytes(word)));
+        }
+        self.index += 8;
+        Ok(Some(u64::from_be_bytes(rest[..8].try_into().unwrap())))
+    }
+}
+
+impl<B: AsRef<[u8]>> SeekSource for Bytes<B> {
+    fn seek_word(&mut self, index: u64) -> Result<(), Error> {
+        let mut len = self.buffer.as_ref().len() as u64;
+        if self.padded {
+            len = len.next_multiple_of(8);
+        }
+        if index.saturating_mul(8) > len {
+            return Err(Error::Truncated);
+        }
+        // the end of a padded stream can be past its last byte
+        self.index = (index as usize * 8).min(self.buffer.as_ref().len());
+        Ok(())
+    }
+}
+
+/// Reads words as 8 big endian bytes, the counterpart of `WriteSink`
+///
+/// Every word is a separate read, so wrap unbuffered readers in a `BufReader`.
+#[derive(Debug)]
+pub struct ReadSource<R: Read> {
+    r: R,
+}
+
+impl<R: Read> ReadSource<R> {
+    pub fn new(r: R) -> Self {
+        ReadSource { r }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+}
+
+impl<R: Read> Source for ReadSource<R> {
+    fn next_word(&mut self) -> Result<Option<u64>, Error> {
+        let mut bytes = [0u8; 8];
+        let mut filled = 0;
+        // read_exact can't tell a clean end apart from a partial word
+        while filled < 8 {
+            match self.r.read(&mut bytes[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::Io(err.kind())),
+            }
+        }
+
+        match filled {
+            0 => Ok(None),
+            8 => Ok(Some(u64::from_be_bytes(bytes))),
+            _ => Err(Error::Truncated),
         }
     }
 }
 
 #[derive(Debug)]
-pub struct OutputBitStream {
-    pub buffer: Vec<u64>,
+pub struct OutputBitStream<S: Sink = Vec<u64>> {
+    pub sink: S,
+    words: u64, // number of words pushed to the sink
     pos: u32,   // position in curr byte; 0 is right-most bit
     curr: u64, // faster than constantly accessing buffer
 }
 
 impl OutputBitStream {
     pub fn new() -> Self {
-        OutputBitStream {
-            buffer: Vec::new(),
-            pos: 0,
-            curr: 0,
-        }
+        OutputBitStream::with_sink(Vec::new())
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        OutputBitStream::with_sink(Vec::with_capacity(capacity))
+    }
+}
+
+impl Default for OutputBitStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> OutputBitStream<WriteSink<W>> {
+    pub fn from_writer(w: W) -> Self {
+        OutputBitStream::with_sink(WriteSink::new(w))
+    }
+}
+
+impl<S: Sink> OutputBitStream<S> {
+    pub fn with_sink(sink: S) -> Self {
         OutputBitStream {
-            buffer: Vec::with_capacity(capacity),
+            sink,
+            words: 0,
             pos: 0,
             curr: 0,
         }
@@ -52,7 +282,8 @@ impl OutputBitStream {
     #[inline(always)]
     fn check_grow(&mut self) {
         if self.pos == 64 {
-            self.buffer.push(self.curr); // increase size
+            self.sink.push(self.curr); // increase size
+            self.words += 1;
             self.pos = 0;
             self.curr = 0;
         }
@@ -60,16 +291,23 @@ impl OutputBitStream {
 
     #[inline(always)]
     fn grow(&mut self) {
-        self.buffer.push(self.curr);
+        self.sink.push(self.curr);
+        self.words += 1;
         self.curr = 0;
     }
 
-    pub fn close(mut self) -> Box<[u64]> {
+    /// exact number of bits written so far
+    pub fn bits_written(&self) -> u64 {
+        self.words * 64 + self.pos as u64
+    }
+
+    /// returns the sink's output and the number of bits written (padded to whole words)
+    pub fn close(mut self) -> (S::Output, u64) {
         // println!("Buffer stats: Bits used: {}", (self.buffer.len() * 64) + self.pos as usize);
         if self.pos != 0 {
-            self.buffer.push(self.curr);
+            self.grow();
         }
-        self.buffer.into_boxed_slice() // FIX?
+        (self.sink.finish(), self.words * 64)
     }
 
     #[inline(always)]
@@ -86,15 +324,16 @@ impl OutputBitStream {
         self.check_grow();
 
         if 64 - self.pos < 8 {
-            self.curr |= (byte >> self.pos) as u64;
+            // bits that don't fit in curr anymore
+            let rest = 8 - (64 - self.pos);
+            self.curr |= byte >> rest;
             self.grow();
-            let byte: u128 = (byte as u128) << self.pos; // to avoid overflow
-            self.curr |= byte as u64;
-            self.pos = (self.pos + 8) & 63;
+            self.curr |= byte << (64 - rest);
+            self.pos = rest;
             return;
         }
 
-        self.curr |= byte << (64 - self.pos);
+        self.curr |= byte << (56 - self.pos);
         self.pos += 8;
     }
 
@@ -109,110 +348,176 @@ impl OutputBitStream {
         self.check_grow();
 
         if 64 - self.pos < len {
-            len -= (64 - self.pos) as u32;
+            len -= 64 - self.pos;
             self.curr |= bits.overflowing_shr(len).0;
             self.grow();
             self.pos = 0;
         }
-        bits <<= (64 - len) - self.pos;
+        // len = 0 on an empty word would shift by 64
+        bits = bits.overflowing_shl((64 - len) - self.pos).0;
         self.curr |= bits;
         self.pos += len;
     }
 }
 
+/// Reads the bits an `OutputBitStream` wrote
+///
+/// Keeps up to 128 bits from the source buffered, so `peek_bits` can look at a whole flag and
+/// header at once and `consume` only has to shift them out. The buffer is refilled a word at a
+/// time, which means up to two words are pulled from the source before they're needed.
 #[derive(Debug)]
-pub struct InputBitStream {
-    pub buffer: Vec<u64>,
-    pub pos: u8,      // where we are in curr byte
-    pub index: usize, // where we are in buffer
-    curr: u64,
+pub struct InputBitStream<S: Source = Words> {
+    pub source: S,
+    buffer: u128, // next bits, left aligned and zero after the valid ones
+    avail: u32,   // number of valid bits in buffer
+    // why the source stopped, only returned once the buffered bits actually run out
+    end: Option<Error>,
 }
 
 impl InputBitStream {
     pub fn new(buffer: Box<[u64]>) -> Self {
-        let buffer = buffer.into_vec();
-        let curr = *buffer.get(0).unwrap();
+        InputBitStream::with_source(Words::new(buffer))
+    }
+}
 
+impl<'a> InputBitStream<Words<&'a [u64]>> {
+    /// reads straight from the slice without copying it
+    pub fn from_slice(words: &'a [u64]) -> Self {
+        InputBitStream::with_source(Words::new(words))
+    }
+}
+
+impl<'a> InputBitStream<Bytes<&'a [u8]>> {
+    /// reads straight from the slice without copying it
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        InputBitStream::with_source(Bytes::new(bytes))
+    }
+}
+
+impl<S: SeekSource> InputBitStream<S> {
+    /// continues reading at bit `bit` of the stream
+    pub fn seek(&mut self, bit: u64) -> Result<(), Error> {
+        self.source.seek_word(bit / 64)?;
+        self.buffer = 0;
+        self.avail = 0;
+        self.end = None;
+        self.consume((bit % 64) as u32)
+    }
+}
+
+impl<R: Read> InputBitStream<ReadSource<R>> {
+    pub fn from_reader(r: R) -> Self {
+        InputBitStream::with_source(ReadSource::new(r))
+    }
+}
+
+impl<S: Source> InputBitStream<S> {
+    pub fn with_source(source: S) -> Self {
+        // starts out empty, words are only pulled from the source once they're needed
         InputBitStream {
-            buffer,
-            pos: 0,
-            index: 0,
-            curr,
+            source,
+            buffer: 0,
+            avail: 0,
+            end: None,
         }
     }
 
-    #[inline(always)]
-    fn check_grow(&mut self) {
-        if self.pos == 64 {
-            self.index += 1;
-            self.pos = 0;
-            self.curr = *self.buffer.get(self.index).ok_or(Error::EOF).unwrap();
+    /// true if there are no bits left to read at all
+    ///
+    /// might have to pull the next word from the source to find out
+    pub fn is_empty(&mut self) -> Result<bool, Error> {
+        if self.avail == 0 {
+            self.refill();
+        }
+        match self.end {
+            _ if self.avail > 0 => Ok(false),
+            None | Some(Error::Truncated) => Ok(true),
+            Some(err) => Err(err),
         }
     }
 
+    // afterwards there are at least 64 bits buffered, unless the source ran out
     #[inline(always)]
-    pub fn read_bit(&mut self) -> Result<Bit, Error> {
-        self.check_grow();
-        self.pos += 1;
-
-        if (self.curr >> (64 - self.pos)) & 1 == 0 {
-            Ok(Bit::Zero)
-        } else {
-            Ok(Bit::One)
+    fn refill(&mut self) {
+        if self.avail > 64 || self.end.is_some() {
+            return;
+        }
+        match self.source.next_word() {
+            Ok(Some(word)) => {
+                self.buffer |= (word as u128) << (64 - self.avail);
+                self.avail += 64;
+            }
+            Ok(None) => self.end = Some(Error::Truncated),
+            Err(err) => self.end = Some(err),
         }
     }
 
-    // can probably remove as well
+    /// the next `len` bits without reading them, len \in [0,64]
+    ///
+    /// bits past the end of the input are zeros, only `consume` notices that they're missing
     #[inline(always)]
-    fn read_byte(&mut self) -> Result<u8, Error> {
-        self.check_grow();
-
-        let mut byte: u8 = 0;
-        if self.pos > 54 {
-            byte |= (self.curr << (64 - self.pos)) as u8;
-            self.index += 1;
-            self.curr = *self.buffer.get(self.index).ok_or(Error::EOF)?;
-
-            byte |= (self.curr >> self.pos) as u8;
-
-            self.pos = (self.pos + 8) & 63;
-            return Ok(byte);
+    pub fn peek_bits(&mut self, len: u32) -> u64 {
+        if self.avail < len {
+            self.refill();
         }
+        ((self.buffer >> 64) as u64).checked_shr(64 - len).unwrap_or(0)
+    }
 
-        let curr_stored = *self.buffer.get(self.index).ok_or(Error::EOF)?;
-
-        byte = (curr_stored >> 54 - self.pos) as u8;
-
-        Ok(byte)
+    /// skips `len` bits, usually after looking at them with `peek_bits`, len \in [0,64]
+    #[inline(always)]
+    pub fn consume(&mut self, len: u32) -> Result<(), Error> {
+        if self.avail < len {
+            self.refill();
+            if self.avail < len {
+                return Err(self.end.unwrap_or(Error::Truncated));
+            }
+        }
+        self.buffer <<= len;
+        self.avail -= len;
+        Ok(())
     }
 
     #[inline(always)]
-    #[allow(unused_mut, unused_variables)]
-    pub fn read_bits(&mut self, mut len: u32) -> Result<u64, Error> {
-        let mut bits: u64 = 0;
-        let bit_mask: u64 = (1 << len - 1) | (1 << len - 1) - 1;
+    pub fn read_bit(&mut self) -> Result<Bit, Error> {
+        let bit = self.peek_bits(1);
+        self.consume(1)?;
+        Ok(if bit == 0 { Bit::Zero } else { Bit::One })
+    }
 
-        self.check_grow();
+    // can probably remove as well
+    #[inline(always)]
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
 
-        if (64 - self.pos) < len as u8 {
-            len -= (64 - self.pos) as u32;
-            bits |= self.curr << len;
-            self.index += 1;
-            self.curr = *self.buffer.get(self.index).ok_or(Error::EOF)?;
-            self.pos = 0;
+    // len \in [0,64]
+    #[inline(always)]
+    pub fn read_bits(&mut self, len: u32) -> Result<u64, Error> {
+        if len > 64 {
+            return Err(Error::InvalidCode);
         }
-
-        self.pos += len as u8;
-        bits |= self.curr >> (64 - self.pos as u32);
-
-        Ok(bits & bit_mask)
+        // peek_bits and consume with only one check for enough bits
+        if self.avail < len {
+            self.refill();
+            if self.avail < len {
+                return Err(self.end.unwrap_or(Error::Truncated));
+            }
+        }
+        let bits = ((self.buffer >> 64) as u64).checked_shr(64 - len).unwrap_or(0);
+        self.buffer <<= len;
+        self.avail -= len;
+        Ok(bits)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::Bytes;
+    use super::Error;
     use super::InputBitStream;
     use super::OutputBitStream;
+    use proptest::prelude::*;
+    use std::io;
     #[test]
     fn write_bit() {
         let mut b = OutputBitStream::new();
@@ -222,14 +527,52 @@ mod tests {
             b.write_bit(i % 2);
         }
         b.grow();
-        assert_eq!(b.buffer[0], 0b0101_0101 << 56);
+        assert_eq!(b.sink[0], 0b0101_0101 << 56);
     }
 
     #[test]
-    fn write_byte() {}
+    fn write_byte() {
+        // every offset, including bytes across word boundaries
+        for offset in 0..64 {
+            let mut b = OutputBitStream::new();
+            b.write_bits(0, offset);
+            for byte in [0xab, 0xff, 0x01, 0x80, 0x5a, 0x00, 0xc3, 0x7e, 0x99] {
+                b.write_byte(byte);
+            }
+            assert_eq!(b.bits_written(), offset as u64 + 72);
+
+            let (words, _) = b.close();
+            let mut r = InputBitStream::new(words);
+            assert_eq!(r.read_bits(offset), Ok(0));
+            for byte in [0xab, 0xff, 0x01, 0x80, 0x5a, 0x00, 0xc3, 0x7e, 0x99] {
+                assert_eq!(r.read_bits(8), Ok(byte));
+            }
+        }
+    }
 
-    #[test]
-    fn write_bits() {}
+    proptest! {
+        #[test]
+        fn write_bits(writes in prop::collection::vec((any::<u64>(), 0..=64u32), 0..200)) {
+            let writes: Vec<(u64, u32)> = writes
+                .into_iter()
+                .map(|(bits, len)| (bits.checked_shr(64 - len).unwrap_or(0), len))
+                .collect();
+
+            let mut b = OutputBitStream::new();
+            for &(bits, len) in &writes {
+                b.write_bits(bits, len);
+            }
+            let total: u64 = writes.iter().map(|&(_, len)| len as u64).sum();
+            prop_assert_eq!(b.bits_written(), total);
+
+            let (words, len) = b.close();
+            prop_assert_eq!(len, total.div_ceil(64) * 64);
+            let mut r = InputBitStream::new(words);
+            for &(bits, len) in &writes {
+                prop_assert_eq!(r.read_bits(len), Ok(bits));
+            }
+        }
+    }
 
     #[test]
     fn write_and_close() {
@@ -253,11 +596,12 @@ mod tests {
         b.write_bits(0b0000_0110, 8);
         b.write_bit(1);
         b.write_bits(0b101, 3);
-        let slice = b.close();
+        let (slice, len) = b.close();
 
         // 1001_0010|0001_0000|0000_0000|0000_1100|1100_0101|1100_0111|0010_0100|1000_0110
         // 0110_1000
         assert_eq!(slice.len(), 2);
+        assert_eq!(len, 128);
 
         let mut r = InputBitStream::new(slice);
         assert_eq!(r.read_bits(4).unwrap(), 0b0100);
@@ -269,15 +613,153 @@ mod tests {
         assert_eq!(r.read_bits(21).unwrap(), 0b11001);
     }
 
+    #[test]
+    fn read_errors() {
+        let mut r = InputBitStream::new(Box::new([]));
+        assert_eq!(r.is_empty(), Ok(true));
+        assert_eq!(r.read_bits(0), Ok(0));
+        assert_eq!(r.read_bit(), Err(Error::Truncated));
+        assert_eq!(r.read_bits(3), Err(Error::Truncated));
+
+        let mut r = InputBitStream::new(Box::new([u64::MAX]));
+        assert_eq!(r.read_bits(65), Err(Error::InvalidCode));
+        assert_eq!(r.read_bits(60), Ok(u64::MAX >> 4));
+        assert_eq!(r.read_bits(0), Ok(0));
+        assert_eq!(r.read_bits(8), Err(Error::Truncated));
+        assert_eq!(r.read_bits(4), Ok(0xf));
+        assert_eq!(r.is_empty(), Ok(true));
+        assert_eq!(r.read_bit(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn peek_consume() {
+        let words = [0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210];
+        let mut r = InputBitStream::from_slice(&words);
+        assert_eq!(r.peek_bits(0), 0);
+        assert_eq!(r.peek_bits(12), 0x012);
+        assert_eq!(r.peek_bits(4), 0x0);
+        r.consume(4).unwrap();
+        assert_eq!(r.peek_bits(64), 0x1234_5678_9abc_deff);
+        r.consume(56).unwrap();
+        // across the word boundary
+        assert_eq!(r.peek_bits(8), 0xff);
+        assert_eq!(r.read_bits(64), Ok(0xfedc_ba98_7654_3210 >> 4 | 0xf << 60));
+        assert_eq!(r.read_bits(4), Ok(0));
+
+        // past the end peeking gives zeros, consuming fails
+        let mut r = InputBitStream::from_slice(&words[..1]);
+        r.consume(60).unwrap();
+        assert_eq!(r.peek_bits(8), 0xf0);
+        assert_eq!(r.consume(8), Err(Error::Truncated));
+        r.consume(4).unwrap();
+        assert_eq!(r.is_empty(), Ok(true));
+
+        // source errors only show up once the bits run out
+        let bytes = [0xab; 12];
+        let mut r = InputBitStream::from_bytes(&bytes);
+        assert_eq!(r.peek_bits(64), u64::from_be_bytes([0xab; 8]));
+        assert_eq!(r.peek_bits(64), u64::from_be_bytes([0xab; 8]));
+        assert_eq!(r.read_bits(64), Ok(u64::from_be_bytes([0xab; 8])));
+        assert_eq!(r.read_bit(), Err(Error::Truncated));
+    }
+
     #[test]
     fn write_read() {
         let mut b = OutputBitStream::new();
         b.write_bits(1.0_f64.to_bits(), 64);
         b.write_bits(0b1011, 4);
 
-        let mut r = InputBitStream::new(b.close());
+        let mut r = InputBitStream::new(b.close().0);
         assert_eq!(r.read_bits(64).unwrap(), 1.0_f64.to_bits());
         assert_eq!(r.read_bits(4).unwrap(), 0b1011);
         assert_eq!(r.read_bits(60).unwrap(), 0);
     }
+
+    #[test]
+    fn write_read_io() {
+        let mut b = OutputBitStream::from_writer(Vec::new());
+        b.write_bits(0xdead_beef, 32);
+        b.write_bits(u64::MAX, 64);
+        b.write_bit(1);
+
+        let (bytes, len) = b.close();
+        let bytes = bytes.unwrap();
+        // big endian words
+        assert_eq!(len, 128);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[..5], [0xde, 0xad, 0xbe, 0xef, 0xff]);
+
+        let mut r = InputBitStream::from_reader(&bytes[..]);
+        assert_eq!(r.is_empty(), Ok(false));
+        assert_eq!(r.read_bits(32), Ok(0xdead_beef));
+        assert_eq!(r.read_bits(64), Ok(u64::MAX));
+        assert_eq!(r.read_bits(2), Ok(0b10));
+        assert_eq!(r.read_bits(30), Ok(0));
+        assert_eq!(r.is_empty(), Ok(true));
+
+        // partial words are an error
+        let mut r = InputBitStream::from_reader(&bytes[..12]);
+        assert_eq!(r.read_bits(64), Ok(0xdead_beef_ffff_ffff));
+        assert_eq!(r.read_bits(64), Err(Error::Truncated));
+
+        struct Broken;
+        impl io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+        }
+        let mut r = InputBitStream::from_reader(Broken);
+        assert_eq!(r.is_empty(), Err(Error::Io(io::ErrorKind::PermissionDenied)));
+    }
+
+    #[test]
+    fn read_borrowed() {
+        let words = [0x0123_4567_89ab_cdef, u64::MAX];
+        let mut r = InputBitStream::from_slice(&words);
+        assert_eq!(r.read_bits(60), Ok(0x0012_3456_789a_bcde));
+        assert_eq!(r.read_bits(8), Ok(0xff));
+        assert_eq!(r.read_bits(60), Ok(u64::MAX >> 4));
+        assert_eq!(r.is_empty(), Ok(true));
+
+        let bytes = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xff];
+        let mut r = InputBitStream::from_bytes(&bytes[..]);
+        assert_eq!(r.read_bits(64), Ok(0x0123_4567_89ab_cdef));
+        assert_eq!(r.read_bit(), Err(Error::Truncated));
+
+        // unaligned start
+        let mut r = InputBitStream::from_bytes(&bytes[1..]);
+        assert_eq!(r.read_bits(8), Ok(0x23));
+
+        // byte padded streams end with zeros instead
+        let mut r = InputBitStream::with_source(Bytes::padded(&bytes[..]));
+        assert_eq!(r.read_bits(64), Ok(0x0123_4567_89ab_cdef));
+        assert_eq!(r.read_bits(8), Ok(0xff));
+        assert_eq!(r.read_bits(56), Ok(0));
+        assert_eq!(r.is_empty(), Ok(true));
+        r.seek(8 * 64).unwrap_err();
+        r.seek(64).unwrap();
+        assert_eq!(r.read_bits(4), Ok(0xf));
+    }
+
+    #[test]
+    fn seek() {
+        let mut b = OutputBitStream::new();
+        for i in 0..100 {
+            b.write_bits(i, 7);
+        }
+        assert_eq!(b.bits_written(), 700);
+        let (words, _) = b.close();
+
+        let mut r = InputBitStream::from_slice(&words);
+        for i in [42, 0, 99, 9, 10, 63] {
+            r.seek(i * 7).unwrap();
+            assert_eq!(r.read_bits(7), Ok(i));
+        }
+        assert_eq!(r.seek(words.len() as u64 * 64 + 1), Err(Error::Truncated));
+
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        let mut r = InputBitStream::from_bytes(&bytes);
+        r.seek(637).unwrap();
+        assert_eq!(r.read_bits(7), Ok(91));
+    }
 }
@@ -0,0 +1,472 @@
+use crate::bitstream::{Error, InputBitStream, Source};
+use crate::{aligned, chimp, chimpn, decimal, gorilla, Decode, Encode, Float};
+use std::io::{self, Read, Write};
+
+// Self-describing container for a compressed buffer
+//
+// layout (all integers big endian):
+// | magic "CHMP" | version: u8 | codec: u8 | float bits: u8 | flags: u8 |
+// | value count: u64 | bit length: u64 | [crc32 of payload: u32] | payload words: u64... |
+pub const MAGIC: [u8; 4] = *b"CHMP";
+pub const VERSION: u8 = 1;
+
+const FLAG_CHECKSUM: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Chimp = 0,
+    ChimpN = 1,
+    Gorilla = 2,
+    Aligned = 3,
+    Decimal = 4,
+}
+
+impl Codec {
+    pub fn from_tag(tag: u8) -> Option<Codec> {
+        match tag {
+            0 => Some(Codec::Chimp),
+            1 => Some(Codec::ChimpN),
+            2 => Some(Codec::Gorilla),
+            3 => Some(Codec::Aligned),
+            4 => Some(Codec::Decimal),
+            _ => None,
+        }
+    }
+
+    /// returns Boxed Buffer and number of bits written
+    pub fn encode<T: Float>(self, values: &[T]) -> (Box<[u64]>, u64) {
+        fn encode_with<T: Float>(
+            mut enc: impl Encode<T, Sink = Vec<u64>>,
+            values: &[T],
+        ) -> (Box<[u64]>, u64) {
+            enc.encode_slice(values);
+            enc.close()
+        }
+
+        match self {
+            Codec::Chimp => encode_with(chimp::Encoder::with_capacity(values.len()), values),
+            Codec::ChimpN => encode_with(chimpn::Encoder::with_capacity(values.len()), values),
+            Codec::Gorilla => encode_with(gorilla::Encoder::new(), values),
+            Codec::Aligned => encode_with(aligned::Encoder::with_capacity(values.len()), values),
+            Codec::Decimal => encode_with(decimal::Encoder::with_capacity(values.len()), values),
+        }
+    }
+
+    /// most values a payload of `bits` bits can hold, any count above that is corrupt
+    pub fn max_count(self, bits: u64) -> u64 {
+        match self {
+            // every block header takes 16 bits, even if all of its values are 0 bits wide
+            Codec::Decimal => bits / 16 * decimal::BLOCK_LEN as u64,
+            // every value takes at least one bit
+            _ => bits,
+        }
+    }
+
+    pub fn decoder<'a, T: Float, S: Source + 'a>(
+        self,
+        r: InputBitStream<S>,
+    ) -> Box<dyn Decode<T> + 'a> {
+        match self {
+            Codec::Chimp => Box::new(chimp::Decoder::new(r)),
+            Codec::ChimpN => Box::new(chimpn::Decoder::new(r)),
+            Codec::Gorilla => Box::new(gorilla::Decoder::new(r)),
+            Codec::Aligned => Box::new(aligned::Decoder::new(r)),
+            Codec::Decimal => Box::new(decimal::Decoder::new(r)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub codec: Codec,
+    pub float_bits: u8,
+    pub count: u64,
+    pub bits: u64,
+    pub checksum: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub header: Header,
+    pub buffer: Box<[u64]>,
+}
+
+impl Block {
+    /// wraps the output of `Encode::close` for `count` values of type `T`
+    pub fn new<T: Float>(codec: Codec, count: u64, (buffer, bits): (Box<[u64]>, u64)) -> Self {
+        Block {
+            header: Header {
+                codec,
+                float_bits: T::BITS as u8,
+                count,
+                bits,
+                checksum: None,
+            },
+            buffer,
+        }
+    }
+
+    pub fn encode<T: Float>(codec: Codec, values: &[T]) -> Self {
+        Block::new::<T>(codec, values.len() as u64, codec.encode(values))
+    }
+
+    /// stores a crc32 of the payload, which is verified in `read_from`
+    pub fn with_checksum(mut self) -> Self {
+        self.header.checksum = Some(crc32(&self.buffer));
+        self
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        let header = &self.header;
+        let flags = if header.checksum.is_some() {
+            FLAG_CHECKSUM
+        } else {
+            0
+        };
+
+        w.write_all(&MAGIC)?;
+        w.write_all(&[VERSION, header.codec as u8, header.float_bits, flags])?;
+        w.write_all(&header.count.to_be_bytes())?;
+        w.write_all(&header.bits.to_be_bytes())?;
+        if let Some(checksum) = header.checksum {
+            w.write_all(&checksum.to_be_bytes())?;
+        }
+        for word in self.buffer.iter() {
+            w.write_all(&word.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut r: impl Read) -> io::Result<Self> {
+        let mut fixed = [0u8; 24];
+        r.read_exact(&mut fixed)?;
+
+        if fixed[..4] != MAGIC {
+            return Err(invalid_data("not a chimp block"));
+        }
+        if fixed[4] != VERSION {
+            return Err(invalid_data("unsupported block version"));
+        }
+        let codec = Codec::from_tag(fixed[5]).ok_or_else(|| invalid_data("unknown codec"))?;
+        let float_bits = fixed[6];
+        if float_bits != 32 && float_bits != 64 {
+            return Err(invalid_data("unsupported float width"));
+        }
+        let flags = fixed[7];
+        let count = u64::from_be_bytes(fixed[8..16].try_into().unwrap());
+        let bits = u64::from_be_bytes(fixed[16..24].try_into().unwrap());
+        if count > codec.max_count(bits) {
+            return Err(invalid_data("more values than the payload can hold"));
+        }
+
+        let checksum = if flags & FLAG_CHECKSUM != 0 {
+            let mut checksum = [0u8; 4];
+            r.read_exact(&mut checksum)?;
+            Some(u32::from_be_bytes(checksum))
+        } else {
+            None
+        };
+
+        // no preallocation, the bit length isn't trusted until the words are actually there
+        let mut buffer = Vec::new();
+        let mut word = [0u8; 8];
+        for _ in 0..bits.div_ceil(64) {
+            r.read_exact(&mut word)?;
+            buffer.push(u64::from_be_bytes(word));
+        }
+        let buffer = buffer.into_boxed_slice();
+
+        if let Some(checksum) = checksum {
+            if crc32(&buffer) != checksum {
+                return Err(invalid_data("checksum mismatch"));
+            }
+        }
+
+        Ok(Block {
+            header: Header {
+                codec,
+                float_bits,
+                count,
+                bits,
+                checksum,
+            },
+            buffer,
+        })
+    }
+
+    /// decoder for the codec in the header, which stops after `count` values
+    pub fn into_decoder<T: Float>(self) -> io::Result<BlockDecoder<'static, T>> {
+        self.header.decoder(InputBitStream::new(self.buffer))
+    }
+
+    /// same as `into_decoder`, but borrows the payload instead
+    pub fn decoder<T: Float>(&self) -> io::Result<BlockDecoder<'_, T>> {
+        self.header.decoder(InputBitStream::from_slice(&self.buffer))
+    }
+}
+
+impl Header {
+    /// decoder for a payload that's stored somewhere else, e.g. a memory-mapped file
+    pub fn decoder<'a, T: Float, S: Source + 'a>(
+        &self,
+        r: InputBitStream<S>,
+    ) -> io::Result<BlockDecoder<'a, T>> {
+        if self.float_bits as u32 != T::BITS {
+            return Err(invalid_data("float width doesn't match the block"));
+        }
+        Ok(BlockDecoder {
+            remaining: self.count,
+            inner: self.codec.decoder(r),
+        })
+    }
+}
+
+pub struct BlockDecoder<'a, T: Float = f64> {
+    remaining: u64,
+    inner: Box<dyn Decode<T> + 'a>,
+}
+
+impl<T: Float> Decode<T> for BlockDecoder<'_, T> {
+    fn get_next(&mut self) -> Result<u64, Error> {
+        if self.remaining == 0 {
+            return Err(Error::EOF);
+        }
+        self.remaining -= 1;
+        match self.inner.get_next() {
+            // stream ended before count values were read
+            Err(Error::EOF) => Err(Error::Truncated),
+            res => res,
+        }
+    }
+
+    // one virtual call per batch instead of one per value
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let len = self.remaining.min(out.len() as u64) as usize;
+        let n = self.inner.decode_into(&mut out[..len])?;
+        self.remaining -= n as u64;
+        if n < len {
+            return Err(Error::Truncated);
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float> Iterator for BlockDecoder<'_, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
+}
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// crc32 (IEEE) over the big endian bytes of the payload
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(words: &[u64]) -> u32 {
+    let mut crc = !0u32;
+    for word in words {
+        for byte in word.to_be_bytes() {
+            crc = (crc >> 8) ^ CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize];
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, Block, Codec};
+    use crate::bitstream::{Error, InputBitStream};
+    use crate::Decode;
+    use std::io;
+
+    const CODECS: [Codec; 5] = [
+        Codec::Chimp,
+        Codec::ChimpN,
+        Codec::Gorilla,
+        Codec::Aligned,
+        Codec::Decimal,
+    ];
+
+    #[test]
+    fn round_trip_test() {
+        let float_vec: Vec<f64> = [
+            49.4, 48.8, 46.4, 47.9, 48.7, 48.9, 48.8, 46.4, 47.9, 48.7, 48.9, f64::NAN,
+        ]
+        .to_vec();
+
+        for codec in CODECS {
+            let mut bytes = Vec::new();
+            Block::encode(codec, &float_vec)
+                .with_checksum()
+                .write_to(&mut bytes)
+                .unwrap();
+
+            let block = Block::read_from(&bytes[..]).unwrap();
+            assert_eq!(block.header.codec, codec);
+            assert_eq!(block.header.count, float_vec.len() as u64);
+
+            // payload starts after the 28 header bytes
+            let decoder = block
+                .header
+                .decoder::<f64, _>(InputBitStream::from_bytes(&bytes[28..]))
+                .unwrap();
+            let datapoints: Vec<u64> = decoder.map(|val| val.unwrap().to_bits()).collect();
+            let expected: Vec<u64> = float_vec.iter().map(|v| v.to_bits()).collect();
+            assert_eq!(datapoints, expected);
+
+            let mut decoder = block.into_decoder::<f64>().unwrap();
+            let mut datapoints = Vec::new();
+            while let Ok(val) = decoder.get_next() {
+                datapoints.push(val);
+            }
+
+            let expected: Vec<u64> = float_vec.iter().map(|v| v.to_bits()).collect();
+            assert_eq!(datapoints, expected);
+
+            // stops after count values even with room for more
+            let block = Block::read_from(&bytes[..]).unwrap();
+            let mut decoder = block.decoder::<f64>().unwrap();
+            let mut out = [0.0; 20];
+            assert_eq!(decoder.decode_into(&mut out[..5]), Ok(5));
+            assert_eq!(decoder.decode_into(&mut out[5..]), Ok(float_vec.len() - 5));
+            assert_eq!(decoder.decode_into(&mut out), Ok(0));
+            let out = &out[..float_vec.len()];
+            assert_eq!(out.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn f32_test() {
+        let float_vec: Vec<f32> = [49.4, 48.8, 46.4, 47.9, 48.7].to_vec();
+
+        for codec in CODECS {
+            let mut bytes = Vec::new();
+            Block::encode(codec, &float_vec)
+                .write_to(&mut bytes)
+                .unwrap();
+
+            let block = Block::read_from(&bytes[..]).unwrap();
+            assert_eq!(block.header.float_bits, 32);
+            assert!(block.clone().into_decoder::<f64>().is_err());
+
+            assert_eq!(
+                block.decoder::<f32>().unwrap().decode_to_vec(),
+                Ok(float_vec.clone())
+            );
+
+            let mut decoder = block.into_decoder::<f32>().unwrap();
+            for val in &float_vec {
+                assert_eq!(decoder.get_next().unwrap() as u32, val.to_bits());
+            }
+            assert!(decoder.get_next().is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_test() {
+        let mut bytes = Vec::new();
+        Block::encode(Codec::Chimp, &[1.0, 2.0, 3.0])
+            .with_checksum()
+            .write_to(&mut bytes)
+            .unwrap();
+
+        // corrupted payload
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(Block::read_from(&corrupt[..]).is_err());
+
+        // unknown codec
+        let mut corrupt = bytes.clone();
+        corrupt[5] = 42;
+        assert!(Block::read_from(&corrupt[..]).is_err());
+
+        // wrong magic
+        let mut corrupt = bytes.clone();
+        corrupt[0] = b'X';
+        assert!(Block::read_from(&corrupt[..]).is_err());
+
+        // truncated
+        assert!(Block::read_from(&bytes[..bytes.len() - 1]).is_err());
+
+        // bit length way past the payload
+        let mut corrupt = bytes.clone();
+        corrupt[16..24].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Block::read_from(&corrupt[..]).is_err());
+
+        // count the payload can't hold
+        let mut corrupt = bytes.clone();
+        corrupt[8..16].copy_from_slice(&u64::MAX.to_be_bytes());
+        let err = Block::read_from(&corrupt[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_test() {
+        let float_vec: Vec<f64> = (0..200)
+            .map(|i| (i as f64 * 0.37).sin() * 10.0 + 100.0)
+            .collect();
+
+        for codec in CODECS {
+            let (buffer, _) = codec.encode(&float_vec);
+            for len in 0..buffer.len() {
+                let mut decoder = codec.decoder::<f64, _>(InputBitStream::from_slice(&buffer[..len]));
+                let res = decoder.decode_to_vec();
+                // an empty buffer is indistinguishable from an empty stream
+                if len == 0 {
+                    assert_eq!(res, Ok(vec![]));
+                } else {
+                    assert_eq!(res, Err(Error::Truncated));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_test() {
+        // xorshift, so the garbage is the same every run
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut garbage = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for codec in CODECS {
+            for len in 1..64 {
+                let buffer: Vec<u64> = (0..len).map(|_| garbage()).collect();
+                let mut decoder = codec.decoder::<f64, _>(InputBitStream::from_slice(&buffer));
+                // only needs to end without panicking
+                while decoder.get_next().is_ok() {}
+            }
+        }
+    }
+
+    #[test]
+    fn crc_test() {
+        // crc32 of "12345678"
+        assert_eq!(crc32(&[u64::from_be_bytes(*b"12345678")]), 0x9AE0_DAAF);
+    }
+}
@@ -0,0 +1,41 @@
+// Checkpoints let decoders start in the middle of a stream instead of decoding everything before
+//
+// encoders record one every K values (see `checkpoint_every`), it holds where the next value
+// starts in the stream and the state the decoder would be in at that point
+
+/// Decoder state right before value `index`, which starts at bit `bit` of the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint<S> {
+    pub index: u64,
+    pub bit: u64,
+    pub state: S,
+}
+
+pub(crate) enum Restart<'a, S> {
+    // keep decoding from the current position
+    Continue,
+    // start over from the beginning of the stream
+    Start,
+    At(&'a Checkpoint<S>),
+}
+
+// where decoding has to restart from to get to value n
+pub(crate) fn restart<S>(checkpoints: &[Checkpoint<S>], position: u64, n: u64) -> Restart<'_, S> {
+    let i = checkpoints.partition_point(|cp| cp.index <= n);
+    let nearest = i.checked_sub(1).map(|i| &checkpoints[i]);
+
+    // going forward is cheapest unless there is a checkpoint in between
+    if position <= n && nearest.is_none_or(|cp| cp.index <= position) {
+        return Restart::Continue;
+    }
+    match nearest {
+        Some(cp) => Restart::At(cp),
+        None => Restart::Start,
+    }
+}
+
+// whether to record a checkpoint after `count` values, `every` = 0 turns them off
+#[inline(always)]
+pub(crate) fn due(every: u64, count: u64) -> bool {
+    every != 0 && count.is_multiple_of(every)
+}
@@ -1,76 +1,151 @@
 use crate::bitstream::*;
-use crate::{Bit, Decode, Encode, LEADING_REPR_DEC, LEADING_REPR_ENC, LEADING_ROUND, NAN};
-use rayon::prelude::*;
+use crate::checkpoint::{self, Checkpoint, Restart};
+use crate::lossy::{self, Bound};
+use crate::simd::{self, Simd};
+use crate::{check_format, nan_bits, Decode, Encode, Float, Format, Seek, LEADING_REPR_DEC};
+use std::marker::PhantomData;
+
+// values converted to bits and xor-ed at a time in `encode_slice`
+const SLICE_CHUNK: usize = 64;
+
+// end of stream is marked with flag 01 and 0 center bits, which can't happen for an actual value
+// (flag 01 is only used when there are more than THRESHOLD trailing zeros). in the reference
+// format it's a NaN instead
+#[inline(always)]
+fn end_of_stream<T: Float>() -> (u64, u32) {
+    (1 << (3 + T::CENTER_BITS), 5 + T::CENTER_BITS)
+}
 
-#[cfg(target_arch = "x86")]
-use std::arch::x86::*;
-#[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
-use std::usize;
+/// Decoder state stored in a checkpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    pub curr: u64,
+    pub leading_zeros: u32,
+}
 
 #[derive(Debug)]
-pub struct Encoder {
+pub struct Encoder<T: Float = f64, S: Sink = Vec<u64>> {
     first: bool,
     curr: u64, // current float value as bits
     leading_zeros: u32,
-    w: OutputBitStream,
+    w: OutputBitStream<S>,
+    count: u64,
+    checkpoint_every: u64,
+    checkpoints: Vec<Checkpoint<State>>,
+    lossy: Option<Bound>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
-impl Encoder {
+impl<T: Float> Encoder<T> {
     pub fn new() -> Self {
-        Encoder {
-            first: true,
-            curr: 0,
-            leading_zeros: u32::MAX,
-            w: OutputBitStream::new(),
-        }
+        Encoder::with_sink(Vec::new())
     }
 
     pub fn with_capacity(capa: usize) -> Self {
+        Encoder::with_sink(Vec::with_capacity(capa))
+    }
+}
+
+impl<T: Float> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
         Encoder {
             first: true,
             curr: 0,
             leading_zeros: u32::MAX,
-            w: OutputBitStream::with_capacity(capa),
+            w: OutputBitStream::with_sink(sink),
+            count: 0,
+            checkpoint_every: 0,
+            checkpoints: Vec::new(),
+            lossy: None,
+            format: Format::Native,
+            float: PhantomData,
         }
     }
 
-    fn insert_first(&mut self, value: f64) {
-        self.curr = value.to_bits();
-        self.w.write_bits(self.curr, 64);
+    /// has to be set before the first value, panics for `Format::Reference` and f32
+    pub fn format(&mut self, format: Format) {
+        assert!(self.first, "format can't change in the middle of a stream");
+        check_format::<T>(format);
+        self.format = format;
     }
 
-    fn insert_value(&mut self, value: f64) {
-        let xor = self.curr ^ value.to_bits();
-        let trailing = xor & 0xff;
+    /// records a checkpoint every `every` values from now on, 0 turns them off
+    pub fn checkpoint_every(&mut self, every: u64) {
+        self.checkpoint_every = every;
+    }
+
+    /// rounds every value encoded from now on as far as `bound` allows, see `lossy::round`
+    pub fn lossy(&mut self, bound: Bound) {
+        self.lossy = Some(bound);
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint<State>] {
+        &self.checkpoints
+    }
+
+    fn state(&self) -> State {
+        State {
+            curr: self.curr,
+            // u32::MAX means nothing was stored yet, the decoder starts at 0 instead
+            leading_zeros: if self.leading_zeros == u32::MAX {
+                0
+            } else {
+                self.leading_zeros
+            },
+        }
+    }
+
+    fn insert_first(&mut self, value: T) {
+        self.curr = value.to_bits64();
+        self.w.write_bits(self.curr, T::BITS);
+    }
+
+    fn insert_value(&mut self, value: T) {
+        let xor = self.curr ^ value.to_bits64();
+        let trailing = xor & ((1 << (T::THRESHOLD + 1)) - 1);
 
         self.enc_aux(xor, trailing);
 
-        self.curr = value.to_bits();
+        self.curr = value.to_bits64();
     }
 
     #[inline(always)]
     fn enc_aux(&mut self, xor: u64, trailing: u64) {
         if xor == 0 {
             self.w.write_bits(0, 2);
-            // self.leading_zeros = 65;  // this line is in the og impl, but not required
+            // the reference forgets the leading zeros here, which only means it can't use flag 10
+            // for the next value
+            if self.format == Format::Reference {
+                self.leading_zeros = u32::MAX;
+            }
             return;
         }
 
-        let lead = LEADING_ROUND[xor.leading_zeros() as usize];
+        let lead = T::LEADING_ROUND[T::leading_zeros(xor) as usize];
 
-        // we and-ed with 0b11_1111
+        // we and-ed with 0b111_1111 (or 0b11_1111 for f32)
         if trailing == 0 {
             let trail = xor.trailing_zeros();
 
             self.w.write_bits(1, 2);
-            self.w.write_bits(LEADING_REPR_ENC[lead as usize] as u64, 3);
+            self.w.write_bits(T::LEADING_REPR_ENC[lead as usize] as u64, 3);
 
-            let center_bits = 64 - lead - trail;
+            let center_bits = T::BITS - lead - trail;
 
-            self.w.write_bits(center_bits as u64, 6);
+            self.w.write_bits(center_bits as u64, T::CENTER_BITS);
             self.w.write_bits(xor >> trail, center_bits);
-            self.leading_zeros = lead;
+            self.leading_zeros = match self.format {
+                Format::Native => lead,
+                Format::Reference => u32::MAX,
+            };
         } else {
             self.w.write_bit(1);
             if lead == self.leading_zeros {
@@ -78,141 +153,132 @@ impl Encoder {
             } else {
                 self.leading_zeros = lead;
                 self.w.write_bit(1);
-                self.w.write_bits(LEADING_REPR_ENC[lead as usize] as u64, 3);
+                self.w.write_bits(T::LEADING_REPR_ENC[lead as usize] as u64, 3);
             }
-            self.w.write_bits(xor, 64 - lead);
+            self.w.write_bits(xor, T::BITS - lead);
         }
     }
 
-    // naive SIMD implementation of chimp
-    // weirdly enough not faster with avx512
-    #[cfg(all(any(target_arch = "x86", target_arch = "x86_64")))]
-    #[target_feature(enable = "avx2")]
-    pub unsafe fn simd_vec(&mut self, values: &Vec<f64>) {
-        let v = values; // bruh moment
+    /// `encode_slice` with the given instruction set, panics if the cpu doesn't have it
+    pub fn encode_slice_with(&mut self, values: &[T], simd: Simd) {
+        let mut values = values;
         if self.first {
-            self.insert_first(values[0]);
-            self.first = false;
+            let Some((&first, rest)) = values.split_first() else {
+                return;
+            };
+            self.encode(first);
+            values = rest;
         }
 
-        let mut i = 0;
-
-        // bitwise operations work can be done across a simd vector (xor, leading/trailing zeroes)
-        //  windows() doesn't work as there would be too many overlapping xor-ed values -> increment i by 4 every iteration
-        while i < values.len() - 4 {
-            // mem::transmute maybe
-            let (a, b, c, d, e) = (v[i], v[i + 1], v[i + 2], v[i + 3], v[i + 4]);
-
-            // lol switch to mm512
-            let xor_vec = _mm256_castpd_si256(_mm256_xor_pd(
-                _mm256_set_pd(a, b, c, d),
-                _mm256_set_pd(b, c, d, e),
-            ));
-
-            // these slow down performance
-            // let xor_vec = _mm256_xor_epi64(
-            //     _mm256_loadu_epi64(v[i..].as_ptr() as *const i64),
-            //     _mm256_loadu_epi64(v[i+1..].as_ptr() as *const i64),
-            // );
-            // let leading_vec = _mm256_lzcnt_epi64(xor_vec);
-
-            // since there is no trailing zero simd intrinsic and we only need to check if bottom
-            // 6 bits are set
-            let trail_threshold = _mm256_and_si256(xor_vec, _mm256_set1_epi64x(0x3f));
-
-            let xor: (u64, u64, u64, u64) /* ,u64, u64, u64, u64) */ = std::mem::transmute(xor_vec);
-            // let leading: (u64, u64, u64, u64) /* ,u64, u64, u64, u64) */ = std::mem::transmute(xor_vec);
-            let trailing: (u64, u64, u64, u64) /* ,u64, u64, u64, u64) */ = std::mem::transmute(trail_threshold);
-
-            // println!("xor:         {:?}", xor);
-            // println!("xor_vec:     {:?}", xor_vec);
-            // println!("leading:     {:?}", leading);
-            // println!("trail thres: {:?}", trail_threshold);
-
-            // self.enc_aux(xor.7, leading.7, trailing.7);
-            // self.enc_aux(xor.6, leading.6, trailing.6);
-            // self.enc_aux(xor.5, leading.5, trailing.5);
-            // self.enc_aux(xor.4, leading.4, trailing.4);
-            self.enc_aux(xor.3, trailing.3);
-            self.enc_aux(xor.2, trailing.2);
-            self.enc_aux(xor.1, trailing.1);
-            self.enc_aux(xor.0, trailing.0);
-
-            i += 4;
-        }
-        self.curr = v[i].to_bits();
-        i += 1;
+        // bits[0] is the value before the chunk
+        let mut bits = [0; SLICE_CHUNK + 1];
+        let mut xors = [0; SLICE_CHUNK];
+        for chunk in values.chunks(SLICE_CHUNK) {
+            bits[0] = self.curr;
+            for (bits, &val) in bits[1..].iter_mut().zip(chunk) {
+                *bits = self.prepare(val).to_bits64();
+            }
+            let xors = &mut xors[..chunk.len()];
+            simd::xor_pairs(simd, &bits[..=chunk.len()], xors);
 
-        // encode rest that don't fit in simd vector
-        while i < values.len() {
-            self.insert_value(values[i]);
-            i += 1;
+            for (&xor, &val) in xors.iter().zip(&bits[1..]) {
+                self.enc_aux(xor, xor & ((1 << (T::THRESHOLD + 1)) - 1));
+                self.curr = val;
+                self.encoded();
+            }
         }
     }
 
-    // how to merge the compressed data?
-    pub fn threaded(values: &Vec<f64>) -> Vec<(Box<[u64]>, u64)> {
-        let encoded: Vec<(Box<[u64]>, u64)> = values
-            .par_iter()
-            // rayon's automatic chunking doens't achieve desirable results at times
-            .fold_chunks(2500, Encoder::new, |mut chimp, &elem| {
-                chimp.encode(elem);
-                chimp
-            })
-            .map(|chimp| chimp.close())
-            .collect();
-        encoded
+    fn prepare(&self, value: T) -> T {
+        let value = match self.lossy {
+            Some(bound) => lossy::round(value, bound),
+            None => value,
+        };
+        assert!(
+            self.format == Format::Native || value.to_bits64() != nan_bits::<T>(),
+            "NaN ends a reference stream"
+        );
+        value
     }
 
-    // NOTE: timestamps?
-}
-
-impl Encode for Encoder {
-    fn encode_vec(values: &Vec<f64>) -> Self {
-        // not much of a gain by guaranteeing a capacity
-        let mut enc = Encoder {
-            first: true,
-            curr: 0,
-            leading_zeros: u32::MAX,
-            w: OutputBitStream::with_capacity(values.len()),
-        };
-        for &val in values {
-            enc.encode(val);
+    fn encoded(&mut self) {
+        self.count += 1;
+        if checkpoint::due(self.checkpoint_every, self.count) {
+            self.checkpoints.push(Checkpoint {
+                index: self.count,
+                bit: self.w.bits_written(),
+                state: self.state(),
+            });
         }
-        enc
     }
+}
 
-    fn encode(&mut self, value: f64) {
+impl<T: Float, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
+
+    fn encode(&mut self, value: T) {
+        let value = self.prepare(value);
         if self.first {
             self.first = false;
             self.insert_first(value);
         } else {
             self.insert_value(value);
         }
+        self.encoded();
+    }
+
+    /// same stream as calling `encode` for every value, with the xors done in the widest simd
+    /// registers the cpu has
+    fn encode_slice(&mut self, values: &[T]) {
+        self.encode_slice_with(values, Simd::detect());
     }
 
-    fn close(self) -> (Box<[u64]>, u64) {
+    fn close(self) -> (S::Output, u64) {
         let mut this = self;
-        this.insert_value(f64::NAN);
-        this.w.write_bit(0); // not sure why actual implementation does this
-        let buffer = this.w.close();
-        let len = &buffer.len() * 64;
-        (buffer, len as u64)
+        match this.format {
+            // empty stream stays empty
+            Format::Native if this.first => {}
+            Format::Native => {
+                let (eos, len) = end_of_stream::<T>();
+                this.w.write_bits(eos, len);
+                this.w.write_bit(0); // the reference writes this bit as well
+            }
+            Format::Reference => {
+                let nan = T::from_bits64(nan_bits::<T>());
+                if this.first {
+                    this.insert_first(nan);
+                } else {
+                    this.insert_value(nan);
+                }
+                this.w.write_bit(0);
+            }
+        }
+        this.w.close()
     }
 }
 
 #[derive(Debug)]
-pub struct Decoder {
+pub struct Decoder<T: Float = f64, S: Source = Words> {
     first: bool,
     done: bool,
     curr: u64, // current float value as bits
     leading_zeros: u32,
     trailing_zeros: u32,
-    r: InputBitStream,
+    r: InputBitStream<S>,
+    position: u64, // number of values decoded
+    checkpoints: Vec<Checkpoint<State>>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
-impl Decoder {
-    pub fn new(read: InputBitStream) -> Self {
+impl<T: Float> Decoder<T> {
+    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
+        Decoder::new(InputBitStream::new(buffer))
+    }
+}
+
+impl<T: Float, S: Source> Decoder<T, S> {
+    pub fn new(read: InputBitStream<S>) -> Self {
         Decoder {
             first: true,
             done: false,
@@ -220,56 +286,75 @@ impl Decoder {
             leading_zeros: 0,
             trailing_zeros: 0,
             r: read,
+            position: 0,
+            checkpoints: Vec::new(),
+            format: Format::Native,
+            float: PhantomData,
         }
     }
 
-    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
-        Decoder {
-            first: true,
-            done: false,
-            curr: 0,
-            leading_zeros: 0,
-            trailing_zeros: 0,
-            r: InputBitStream::new(buffer),
-        }
+    /// e.g. `Format::Reference` for streams written by the reference implementation, those are
+    /// only padded to whole bytes, see `Bytes::padded`
+    pub fn format(&mut self, format: Format) {
+        check_format::<T>(format);
+        self.format = format;
+    }
+
+    /// checkpoints recorded by the encoder, used by `seek`
+    pub fn set_checkpoints(&mut self, checkpoints: Vec<Checkpoint<State>>) {
+        self.checkpoints = checkpoints;
     }
 
     fn get_first(&mut self) -> Result<(), Error> {
-        self.curr = self.r.read_bits(64)?;
+        // nothing was encoded
+        if self.r.is_empty()? {
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        self.curr = self.r.read_bits(T::BITS)?;
         Ok(())
     }
 
     fn get_value(&mut self) -> Result<(), Error> {
-        let mut center_bits: u32;
-        let xor: u64;
-        match self.r.read_bits(2)? {
+        // flag, leading zeros and center bits of flag 01 all in one go
+        let header_len = 5 + T::CENTER_BITS;
+        let header = self.r.peek_bits(header_len);
+        let center_bits: u32;
+        match header >> (header_len - 2) {
             1 => {
-                self.leading_zeros = LEADING_REPR_DEC[self.r.read_bits(3)? as usize];
-                center_bits = self.r.read_bits(6)? as u32;
-                if center_bits == 0 {
-                    center_bits = 64;
+                self.r.consume(header_len)?;
+                self.leading_zeros = LEADING_REPR_DEC[(header >> T::CENTER_BITS) as usize & 7];
+                center_bits = match header as u32 & ((1 << T::CENTER_BITS) - 1) {
+                    0 if self.format == Format::Native => {
+                        self.done = true;
+                        return Err(Error::EOF);
+                    }
+                    // the reference decoder reads it as all center bits
+                    0 => T::BITS,
+                    center_bits => center_bits,
+                };
+                if center_bits + self.leading_zeros > T::BITS {
+                    return Err(Error::InvalidCode);
                 }
-                self.trailing_zeros = 64 - center_bits - self.leading_zeros;
-                xor = self.r.read_bits(center_bits)?;
-                self.curr ^= xor << self.trailing_zeros;
+                self.trailing_zeros = T::BITS - center_bits - self.leading_zeros;
+                self.curr ^= self.r.read_bits(center_bits)? << self.trailing_zeros;
             }
             2 => {
-                center_bits = 64 - self.leading_zeros;
-                xor = self.r.read_bits(center_bits)?;
-                self.curr ^= xor;
+                self.r.consume(2)?;
+                center_bits = T::BITS - self.leading_zeros;
+                self.curr ^= self.r.read_bits(center_bits)?;
             }
             3 => {
-                self.leading_zeros = LEADING_REPR_DEC[self.r.read_bits(3)? as usize];
-                center_bits = 64 - self.leading_zeros;
-                xor = self.r.read_bits(center_bits)?;
-                self.curr ^= xor;
+                self.r.consume(5)?;
+                self.leading_zeros = LEADING_REPR_DEC[(header >> (header_len - 5)) as usize & 7];
+                center_bits = T::BITS - self.leading_zeros;
+                self.curr ^= self.r.read_bits(center_bits)?;
             }
-            _ => {} // unreachable!("bruh moment"),
+            _ => self.r.consume(2)?, // identical value
         }
         Ok(())
     }
 
-    // implement iterator?
     pub fn get_next(&mut self) -> Result<u64, Error> {
         if self.done {
             return Err(Error::EOF);
@@ -281,45 +366,93 @@ impl Decoder {
         } else {
             self.get_value()?;
         }
-
-        if self.curr == NAN {
+        if self.format == Format::Reference && self.curr == nan_bits::<T>() {
             self.done = true;
-            Err(Error::EOF)
-        } else {
-            Ok(self.curr)
+            return Err(Error::EOF);
         }
+
+        self.position += 1;
+        Ok(self.curr)
     }
+}
 
-    // not optimized at all
-    pub fn decode_threaded(values: Vec<(Box<[u64]>, u64)>) -> Vec<f64> {
-        values
-            .par_iter()
-            .map(|pair| &pair.0)
-            .fold(Vec::<f64>::new, |mut vec, buffer| {
-                let mut dec = Decoder::from_buffer(buffer.clone()); // wat da heo
-                while let Ok(bits) = dec.get_next() {
-                    vec.push(f64::from_bits(bits));
-                }
-                vec
-            })
-            .reduce(Vec::<f64>::new, |mut acc, mut list| {
-                acc.append(&mut list);
-                acc
-            })
+impl<T: Float, S: SeekSource> Seek<T> for Decoder<T, S> {
+    fn seek(&mut self, n: u64) -> Result<(), Error> {
+        match checkpoint::restart(&self.checkpoints, self.position, n) {
+            Restart::Continue => {}
+            Restart::Start => {
+                self.r.seek(0)?;
+                self.first = true;
+                self.done = false;
+                self.leading_zeros = 0;
+                self.position = 0;
+            }
+            Restart::At(cp) => {
+                self.r.seek(cp.bit)?;
+                self.first = false;
+                self.done = false;
+                self.curr = cp.state.curr;
+                self.leading_zeros = cp.state.leading_zeros;
+                self.position = cp.index;
+            }
+        }
+
+        while self.position < n {
+            self.get_next()?;
+        }
+        Ok(())
     }
 }
 
-impl Decode for Decoder {
+impl<T: Float, S: Source> Decode<T> for Decoder<T, S> {
     fn get_next(&mut self) -> Result<u64, Error> {
         self.get_next()
     }
+
+    // only the first value and the nan end marker of the reference format need get_next's
+    // checks, everything else goes straight from get_value into `out`
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let mut n = 0;
+        while n < out.len() && (self.first || self.format == Format::Reference) {
+            match self.get_next() {
+                Ok(bits) => out[n] = T::from_bits64(bits),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            n += 1;
+        }
+        if self.done {
+            return Ok(n);
+        }
+
+        for val in &mut out[n..] {
+            match self.get_value() {
+                Ok(()) => *val = T::from_bits64(self.curr),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            self.position += 1;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
 }
 
 #[cfg(test)]
 mod chimp_tests {
     use super::{Decoder, Encoder};
-    use crate::bitstream::InputBitStream;
-    use crate::Encode;
+    use crate::bitstream::{Bytes, Error, InputBitStream};
+    use crate::lossy::Bound;
+    use crate::simd::Simd;
+    use crate::{reference, Encode, Format};
 
     #[test]
     fn simple_test() {
@@ -337,7 +470,7 @@ mod chimp_tests {
         }
 
         let (bytes, _) = encoder.close();
-        let mut decoder = Decoder::new(InputBitStream::new(bytes));
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
         let mut datapoints = Vec::new();
 
         while let Ok(val) = decoder.get_next() {
@@ -354,22 +487,92 @@ mod chimp_tests {
             49.4, 48.8, 46.4, 47.9, 48.7, 48.9, 48.8, 46.4, 47.9, 48.7, 48.9, 48.1, 48.12, 1., 2.,
             0.3,
         ]
-        .to_vec();
+        .iter()
+        .cycle()
+        .take(300)
+        .copied()
+        .collect();
+
+        for simd in Simd::ALL.into_iter().filter(|simd| simd.is_available()) {
+            // short slices, ones that end mid chunk and a stream that was already started
+            for len in [0, 1, 2, 4, 5, 9, 64, 65, 130, 300] {
+                let values = &float_vec[..len];
+                let mut encoder = Encoder::<f64>::new();
+                encoder.encode_slice(values);
+                let (expected, _) = encoder.close();
+                let mut encoder = Encoder::new();
+                encoder.encode_slice_with(values, simd);
+                assert_eq!(encoder.close().0, expected, "{simd:?} {len}");
+
+                if len > 0 {
+                    let mut encoder = Encoder::new();
+                    encoder.encode(values[0]);
+                    let (a, b) = values[1..].split_at(len / 2);
+                    encoder.encode_slice_with(a, simd);
+                    encoder.encode_slice_with(b, simd);
+                    assert_eq!(encoder.close().0, expected, "{simd:?} {len}");
+                }
+            }
 
-        let mut encoder = Encoder::new();
-        unsafe {
-            encoder.simd_vec(&float_vec);
+            // checkpoints, lossy and f32 go through the same code
+            let mut expected = Encoder::new();
+            let mut encoder = Encoder::new();
+            for enc in [&mut expected, &mut encoder] {
+                enc.checkpoint_every(10);
+                enc.lossy(Bound::Absolute(0.01));
+            }
+            for &val in &float_vec {
+                expected.encode(val);
+            }
+            encoder.encode_slice_with(&float_vec, simd);
+            assert_eq!(encoder.checkpoints(), expected.checkpoints());
+            assert_eq!(encoder.close().0, expected.close().0);
+
+            let f32_vec: Vec<f32> = float_vec.iter().map(|&val| val as f32).collect();
+            let mut encoder = Encoder::<f32>::new();
+            encoder.encode_slice(&f32_vec);
+            let (expected, _) = encoder.close();
+            let mut encoder = Encoder::new();
+            encoder.encode_slice_with(&f32_vec, simd);
+            assert_eq!(encoder.close().0, expected, "{simd:?}");
         }
+    }
 
-        let (bytes, _) = encoder.close();
-        let mut decoder = Decoder::new(InputBitStream::new(bytes));
-        let mut datapoints = Vec::new();
-
-        while let Ok(val) = decoder.get_next() {
-            datapoints.push(f64::from_bits(val));
-            // println!("value: {}", f64::from_bits(val));
+    #[test]
+    fn reference_test() {
+        let values: Vec<f64> = reference::values().into_iter().map(f64::from_bits).collect();
+        let mut encoder = Encoder::new();
+        encoder.format(Format::Reference);
+        for &val in &values {
+            encoder.encode(val);
         }
+        reference::assert_stream(encoder.close(), "chimp.bin");
+
+        let mut encoder = Encoder::<f64>::new();
+        encoder.format(Format::Reference);
+        reference::assert_stream(encoder.close(), "chimp_empty.bin");
+
+        let bytes = reference::fixture("chimp.bin");
+        let mut decoder: Decoder<f64, _> =
+            Decoder::new(InputBitStream::with_source(Bytes::padded(&bytes)));
+        decoder.format(Format::Reference);
+        for &val in &values {
+            assert_eq!(decoder.get_next(), Ok(val.to_bits()));
+        }
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
 
-        assert_eq!(datapoints, float_vec);
+        let bytes = reference::fixture("chimp_empty.bin");
+        let mut decoder: Decoder<f64, _> =
+            Decoder::new(InputBitStream::with_source(Bytes::padded(&bytes)));
+        decoder.format(Format::Reference);
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    #[should_panic]
+    fn reference_nan_test() {
+        let mut encoder = Encoder::new();
+        encoder.format(Format::Reference);
+        encoder.encode(f64::NAN);
     }
 }
@@ -1,237 +1,425 @@
+use crate::bitstream::{SeekSource, Source, Words};
+use crate::checkpoint::{self, Checkpoint, Restart};
+use crate::lossy::{self, Bound};
 use crate::*;
+use std::marker::PhantomData;
+
+// Chimp N, N = 2^log_n previous values are kept in a ring buffer
+pub const DEFAULT_WINDOW: usize = 128;
+// log_n is stored in the first bits of the stream, so the decoder doesn't need to be told. the
+// reference format doesn't have it, so there the decoder needs the window (`with_window`)
+pub(crate) const LOG_N_BITS: u32 = 4;
+const MAX_LOG_N: u32 = 12;
+
+/// trailing zeros a value with the same lsb needs before it's used as reference
+/// (= 13 for f64 and N = 128)
+pub const fn threshold<T: Float>(log_n: u32) -> u32 {
+    T::THRESHOLD + log_n
+}
+
+/// mask for the lsb used to look up previous values (= 0x3FFF for f64 and N = 128)
+pub const fn lsb_mask<T: Float>(log_n: u32) -> u64 {
+    (1 << (threshold::<T>(log_n) + 1)) - 1
+}
+
+// flag 01 with 0 center bits marks the end of the stream, flag 01 always has more than
+// threshold trailing zeros so this can't be an actual value. in the reference format it's a NaN
+#[inline(always)]
+fn end_of_stream<T: Float>(log_n: u32) -> (u64, u32) {
+    (1 << (log_n + 3 + T::CENTER_BITS), log_n + 5 + T::CENTER_BITS)
+}
 
-// Chimp N (= 128)
-pub const THRESHOLD: u32 = 13;
-pub const LSB_MASK: u64 = 0x3FFF;
+// log_2 of a window size, panics for windows that can't be stored
+fn window_log_n(n: usize) -> u32 {
+    assert!(
+        n.is_power_of_two() && (2..=1 << MAX_LOG_N).contains(&n),
+        "window has to be a power of two in [2, 4096]"
+    );
+    n.trailing_zeros()
+}
 
-pub struct Encoder {
+#[inline(always)]
+fn check_log_n(log_n: u32) -> Result<u32, Error> {
+    if log_n == 0 || log_n > MAX_LOG_N {
+        return Err(Error::InvalidCode);
+    }
+    Ok(log_n)
+}
+
+/// Decoder state stored in a checkpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    /// ring buffer of previous values, `curr_idx` is the latest one
+    pub stored_vals: Box<[u64]>,
+    pub curr_idx: usize,
+    pub leading_zeros: u32,
+}
+
+pub struct Encoder<T: Float = f64, S: Sink = Vec<u64>> {
     first: bool,
+    log_n: u32,
     stored_vals: Vec<u64>,
     indices: Vec<usize>,
 
     leading_zeros: u32,
     curr_idx: usize,
     index: usize, // always points to previous index
-    w: OutputBitStream,
+    w: OutputBitStream<S>,
+    count: u64,
+    checkpoint_every: u64,
+    checkpoints: Vec<Checkpoint<State>>,
+    lossy: Option<Bound>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
-impl Encoder {
+impl<T: Float> Encoder<T> {
     pub fn new() -> Self {
-        Encoder {
-            first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![0; 2_usize.pow(14)],
-            leading_zeros: 0,
-            curr_idx: 0,
-            index: 0,
-            w: OutputBitStream::new(),
-        }
+        Encoder::with_window(DEFAULT_WINDOW)
     }
 
     pub fn with_capacity(capa: usize) -> Self {
+        Encoder::with_sink_and_window(Vec::with_capacity(capa), DEFAULT_WINDOW)
+    }
+
+    /// keeps the last `n` values as possible references, `n` is a power of two in [2, 4096]
+    pub fn with_window(n: usize) -> Self {
+        Encoder::with_sink_and_window(Vec::new(), n)
+    }
+}
+
+impl<T: Float> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
+        Encoder::with_sink_and_window(sink, DEFAULT_WINDOW)
+    }
+
+    pub fn with_sink_and_window(sink: S, n: usize) -> Self {
+        let log_n = window_log_n(n);
+
         Encoder {
             first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![usize::MAX; 2_usize.pow(14)],
-            leading_zeros: 0,
+            log_n,
+            stored_vals: vec![0; n],
+            indices: vec![usize::MAX; lsb_mask::<T>(log_n) as usize + 1],
+            // same as the decoder, the first flag 10 can only come after a flag 11 or 01
+            leading_zeros: u32::MAX,
             curr_idx: 0,
             index: 0,
-            w: OutputBitStream::with_capacity(capa),
+            w: OutputBitStream::with_sink(sink),
+            count: 0,
+            checkpoint_every: 0,
+            checkpoints: Vec::new(),
+            lossy: None,
+            format: Format::Native,
+            float: PhantomData,
         }
     }
 
-    fn insert_first(&mut self, value: f64) {
-        self.stored_vals[self.index] = value.to_bits();
-        self.indices[(value.to_bits() & LSB_MASK) as usize] = self.index;
+    /// has to be set before the first value, panics for `Format::Reference` and f32
+    pub fn format(&mut self, format: Format) {
+        assert!(self.first, "format can't change in the middle of a stream");
+        check_format::<T>(format);
+        self.format = format;
+    }
+
+    /// records a checkpoint every `every` values from now on, 0 turns them off
+    pub fn checkpoint_every(&mut self, every: u64) {
+        self.checkpoint_every = every;
+    }
+
+    /// rounds every value encoded from now on as far as `bound` allows, see `lossy::round`
+    pub fn lossy(&mut self, bound: Bound) {
+        self.lossy = Some(bound);
+    }
 
-        self.w.write_bits(value.to_bits(), 64);
+    pub fn checkpoints(&self) -> &[Checkpoint<State>] {
+        &self.checkpoints
+    }
+
+    fn state(&self) -> State {
+        State {
+            stored_vals: self.stored_vals.clone().into_boxed_slice(),
+            curr_idx: self.curr_idx,
+            leading_zeros: self.leading_zeros,
+        }
+    }
+
+    fn insert_first(&mut self, value: T) {
+        let value = value.to_bits64();
+        self.stored_vals[self.index] = value;
+        self.indices[(value & lsb_mask::<T>(self.log_n)) as usize] = self.index;
+
+        if self.format == Format::Native {
+            self.w.write_bits(self.log_n as u64, LOG_N_BITS);
+        }
+        self.w.write_bits(value, T::BITS);
     }
 
     #[inline(always)]
-    fn insert_value(&mut self, value: f64) {
+    fn insert_value(&mut self, value: T) {
+        let value = value.to_bits64();
+        let log_n = self.log_n;
+        let window = self.stored_vals.len();
         let prev_index: usize;
         let mut trail: u32 = 0;
         let mut xor: u64;
 
-        let lsb_index: usize = self.indices[(value.to_bits() & LSB_MASK) as usize];
+        let lsb_index: usize = self.indices[(value & lsb_mask::<T>(log_n)) as usize];
 
         // if value with same lsb is still in scope
-        if lsb_index <= self.index && (self.index - lsb_index) < 128 {
-            xor = value.to_bits() ^ self.stored_vals[lsb_index & 127];
+        if lsb_index <= self.index && (self.index - lsb_index) < window {
+            xor = value ^ self.stored_vals[lsb_index & (window - 1)];
             trail = xor.trailing_zeros();
 
             // technically shouldn't need to check this?
-            if trail > THRESHOLD {
-                prev_index = lsb_index & 127;
+            if trail > threshold::<T>(log_n) {
+                prev_index = lsb_index & (window - 1);
             } else {
                 // previous value
-                prev_index = self.index & 127;
-                xor = self.stored_vals[self.curr_idx] ^ value.to_bits();
+                prev_index = self.index & (window - 1);
+                xor = self.stored_vals[self.curr_idx] ^ value;
             }
         } else {
-            prev_index = self.index & 127;
-            xor = self.stored_vals[self.curr_idx] ^ value.to_bits();
+            prev_index = self.index & (window - 1);
+            xor = self.stored_vals[self.curr_idx] ^ value;
         }
 
         // identical value
         // flag: 00
         if xor == 0 {
-            self.w.write_bits(prev_index as u64, 9); // 'flagZeroSize' = log_2(ring_buffer_size) + 2
-            // self.leading_zeros = 65;
+            self.w.write_bits(prev_index as u64, log_n + 2); // 'flagZeroSize' = log_2(ring_buffer_size) + 2
+            // the reference forgets the leading zeros here, which only means it can't use flag 10
+            // for the next value
+            if self.format == Format::Reference {
+                self.leading_zeros = u32::MAX;
+            }
         } else {
-            let lead = LEADING_ROUND[xor.leading_zeros() as usize];
+            let lead = T::LEADING_ROUND[T::leading_zeros(xor) as usize];
 
             // flag: 01
-            if trail > THRESHOLD {
-                let center_bits = u64::from(64 - lead - trail);
+            if trail > threshold::<T>(log_n) {
+                let center_bits = u64::from(T::BITS - lead - trail);
 
-                let tmp = (128 | prev_index as u64) << 9
-                    | (LEADING_REPR_ENC[lead as usize] as u64) << 6
+                let tmp = ((1 << log_n) | prev_index as u64) << (3 + T::CENTER_BITS)
+                    | (T::LEADING_REPR_ENC[lead as usize] as u64) << T::CENTER_BITS
                     | center_bits;
 
-                self.w.write_bits(tmp, 18); // flagOneSize = log_2(ring_buffer_size) + 11
+                // flagOneSize = log_2(ring_buffer_size) + 11
+                self.w.write_bits(tmp, log_n + 5 + T::CENTER_BITS);
                 self.w.write_bits(xor >> trail, center_bits as u32);
 
-                self.leading_zeros = lead;
+                self.leading_zeros = match self.format {
+                    Format::Native => lead,
+                    Format::Reference => u32::MAX,
+                };
             } else {
-                let center_bits = 64 - lead;
+                let center_bits = T::BITS - lead;
 
                 if lead != self.leading_zeros {
                     self.leading_zeros = lead;
 
                     self.w.write_bits(3, 2); // flag: 11
-                    self.w.write_bits(LEADING_REPR_ENC[lead as usize] as u64, 3)
+                    self.w.write_bits(T::LEADING_REPR_ENC[lead as usize] as u64, 3)
                 } else {
                     self.w.write_bits(2, 2); // flag: 10
                 }
 
                 self.w.write_bits(xor, center_bits);
-
             }
         }
 
         self.curr_idx += 1;
-        self.curr_idx &= 127;
+        self.curr_idx &= window - 1;
 
-        self.stored_vals[self.curr_idx] = value.to_bits();
+        self.stored_vals[self.curr_idx] = value;
 
         self.index += 1;
-        self.indices[(value.to_bits() & LSB_MASK) as usize] = self.index;
+        self.indices[(value & lsb_mask::<T>(log_n)) as usize] = self.index;
     }
 }
 
-impl Encode for Encoder {
-    fn encode_vec(values: &Vec<f64>) -> Self {
-        let mut chimpn = Encoder {
-            first: true,
-            stored_vals: vec![0; 128],
-            indices: vec![0; 2_usize.pow(14)],
-            leading_zeros: 0,
-            curr_idx: 0,
-            index: 0,
-            w: OutputBitStream::with_capacity(values.len() / 2),
-        };
-        for &val in values {
-            chimpn.encode(val);
-        }
-        chimpn
-    }
+impl<T: Float, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
 
-    fn encode(&mut self, value: f64) {
+    fn encode(&mut self, value: T) {
+        let value = match self.lossy {
+            Some(bound) => lossy::round(value, bound),
+            None => value,
+        };
+        assert!(
+            self.format == Format::Native || value.to_bits64() != nan_bits::<T>(),
+            "NaN ends a reference stream"
+        );
         if self.first {
             self.first = false;
             self.insert_first(value);
         } else {
             self.insert_value(value);
         }
+
+        self.count += 1;
+        if checkpoint::due(self.checkpoint_every, self.count) {
+            self.checkpoints.push(Checkpoint {
+                index: self.count,
+                bit: self.w.bits_written(),
+                state: self.state(),
+            });
+        }
     }
 
-    fn close(self) -> (Box<[u64]>, u64) {
+    fn close(self) -> (S::Output, u64) {
         let mut this = self;
-        this.insert_value(f64::NAN);
-        this.w.write_bit(0); // not sure why actual implementation does this
-        let buffer = this.w.close();
-        let len = &buffer.len() * 64;
-        (buffer, len as u64)
+        match this.format {
+            // empty stream stays empty
+            Format::Native if this.first => {}
+            Format::Native => {
+                let (eos, len) = end_of_stream::<T>(this.log_n);
+                this.w.write_bits(eos, len);
+                this.w.write_bit(0); // the reference writes this bit as well
+            }
+            Format::Reference => {
+                let nan = T::from_bits64(nan_bits::<T>());
+                if this.first {
+                    this.insert_first(nan);
+                } else {
+                    this.insert_value(nan);
+                }
+                this.w.write_bit(0);
+            }
+        }
+        this.w.close()
     }
 }
 
-pub struct Decoder {
+pub struct Decoder<T: Float = f64, S: Source = Words> {
     first: bool,
     done: bool,
 
+    log_n: u32, // read from the stream, unless it's in the reference format
     stored_vals: Vec<u64>,
     curr: u64, // curr stored value
     curr_idx: usize,
     leading_zeros: u32,
-    r: InputBitStream,
+    r: InputBitStream<S>,
+    position: u64, // number of values decoded
+    checkpoints: Vec<Checkpoint<State>>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
-// prev_values = 128
-// prev_values_log = 7
-// initial_fill = 7 + 9 = 16
-impl Decoder {
-    pub fn new(r: InputBitStream) -> Self {
+impl<T: Float, S: Source> Decoder<T, S> {
+    pub fn new(r: InputBitStream<S>) -> Self {
+        Decoder::with_window(r, DEFAULT_WINDOW)
+    }
+
+    /// window for streams in the reference format, streams in this crate's format store their own
+    pub fn with_window(r: InputBitStream<S>, n: usize) -> Self {
         Decoder {
             first: true,
             done: false,
-            stored_vals: (0..128).collect(),
+            log_n: window_log_n(n),
+            stored_vals: Vec::new(),
             curr: 0,
             curr_idx: 0,
             leading_zeros: u32::MAX,
             r,
+            position: 0,
+            checkpoints: Vec::new(),
+            format: Format::Native,
+            float: PhantomData,
         }
     }
 
+    /// e.g. `Format::Reference` for streams written by the reference implementation, those are
+    /// only padded to whole bytes, see `Bytes::padded`
+    pub fn format(&mut self, format: Format) {
+        check_format::<T>(format);
+        self.format = format;
+    }
+
+    /// checkpoints recorded by the encoder, used by `seek`
+    pub fn set_checkpoints(&mut self, checkpoints: Vec<Checkpoint<State>>) {
+        self.checkpoints = checkpoints;
+    }
+
     fn get_first(&mut self) -> Result<(), Error> {
-        self.curr = self.r.read_bits(64)?;
+        // nothing was encoded
+        if self.r.is_empty()? {
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        if self.format == Format::Native {
+            self.log_n = check_log_n(self.r.read_bits(LOG_N_BITS)? as u32)?;
+        }
+        self.stored_vals = (0..1 << self.log_n).collect();
+
+        self.curr = self.r.read_bits(T::BITS)?;
         self.stored_vals[self.curr_idx] = self.curr;
         Ok(())
     }
 
     fn get_value(&mut self) -> Result<(), Error> {
-        let xor: u64;
+        // flag, index, leading zeros and center bits of flag 01 all in one go, at most 23 bits
+        let header_len = 2 + self.log_n + 3 + T::CENTER_BITS;
+        let header = self.r.peek_bits(header_len);
 
-        match self.r.read_bits(2)? {
+        match header >> (header_len - 2) {
             1 => {
-                // prev_values = 128
-                // prev_values_log = 7
-                // initial_fill = 7 + 9 = 16
-                let mut tmp = self.r.read_bits(16)?;
-                let mut center_bits = tmp & 0x3F;
-                tmp >>= 6;
+                // initial_fill = log_n + 9 (= 16 for N = 128)
+                self.r.consume(header_len)?;
+                let center_bits = header & ((1 << T::CENTER_BITS) - 1);
+                let tmp = header >> T::CENTER_BITS;
 
                 self.leading_zeros = LEADING_REPR_DEC[(tmp & 7) as usize];
-                tmp >>= 3;
-
-                let index = tmp & ((1 << 7) - 1);
+                let index = (tmp >> 3) & ((1 << self.log_n) - 1);
                 self.curr = self.stored_vals[index as usize];
 
-                if center_bits == 0 {
-                    center_bits = 64;
+                let center_bits = match center_bits {
+                    0 if self.format == Format::Native => {
+                        self.done = true;
+                        return Err(Error::EOF);
+                    }
+                    // the reference decoder reads it as all center bits
+                    0 => T::BITS as u64,
+                    center_bits => center_bits,
+                };
+                if center_bits as u32 + self.leading_zeros > T::BITS {
+                    return Err(Error::InvalidCode);
                 }
 
-                let trailing_zeros = 64 - center_bits as u32 - self.leading_zeros;
-                xor = self.r.read_bits(center_bits as u32)?;
-                self.curr ^= xor << trailing_zeros;
+                let trailing_zeros = T::BITS - center_bits as u32 - self.leading_zeros;
+                self.curr ^= self.r.read_bits(center_bits as u32)? << trailing_zeros;
             }
             2 => {
-                xor = self.r.read_bits(64 - self.leading_zeros)?;
-                self.curr ^= xor;
+                self.r.consume(2)?;
+                // no leading zeros were stored yet
+                if self.leading_zeros > T::BITS {
+                    return Err(Error::InvalidCode);
+                }
+                self.curr ^= self.r.read_bits(T::BITS - self.leading_zeros)?;
             }
             3 => {
-                self.leading_zeros = LEADING_REPR_DEC[self.r.read_bits(3)? as usize];
-                xor = self.r.read_bits(64 - self.leading_zeros)?;
-                self.curr ^= xor;
+                self.r.consume(5)?;
+                let lead = (header >> (header_len - 5)) & 7;
+                self.leading_zeros = LEADING_REPR_DEC[lead as usize];
+                self.curr ^= self.r.read_bits(T::BITS - self.leading_zeros)?;
             }
             _ => {
-                let index = self.r.read_bits(7)? as usize;
-                self.curr = self.stored_vals[index];
+                self.r.consume(2 + self.log_n)?;
+                let index = (header >> (header_len - 2 - self.log_n)) & ((1 << self.log_n) - 1);
+                self.curr = self.stored_vals[index as usize];
             }
         }
 
         self.curr_idx += 1;
-        self.curr_idx %= 128;
+        self.curr_idx &= (1 << self.log_n) - 1;
         self.stored_vals[self.curr_idx] = self.curr;
 
         Ok(())
@@ -248,26 +436,125 @@ impl Decoder {
         } else {
             self.get_value()?;
         }
+        if self.format == Format::Reference && self.curr == nan_bits::<T>() {
+            self.done = true;
+            return Err(Error::EOF);
+        }
 
-        if self.curr == NAN {
-            Err(Error::EOF)
-        } else {
-            Ok(self.curr)
+        self.position += 1;
+        Ok(self.curr)
+    }
+}
+
+impl<T: Float, S: SeekSource> Seek<T> for Decoder<T, S> {
+    fn seek(&mut self, n: u64) -> Result<(), Error> {
+        match checkpoint::restart(&self.checkpoints, self.position, n) {
+            Restart::Continue => {}
+            Restart::Start => {
+                self.r.seek(0)?;
+                self.first = true;
+                self.done = false;
+                self.curr_idx = 0;
+                self.leading_zeros = u32::MAX;
+                self.position = 0;
+            }
+            Restart::At(cp) => {
+                let window = cp.state.stored_vals.len();
+                if !window.is_power_of_two() {
+                    return Err(Error::InvalidCode);
+                }
+                self.log_n = check_log_n(window.trailing_zeros())?;
+                self.r.seek(cp.bit)?;
+                self.first = false;
+                self.done = false;
+                self.stored_vals = cp.state.stored_vals.to_vec();
+                self.curr_idx = cp.state.curr_idx % self.stored_vals.len();
+                self.curr = self.stored_vals[self.curr_idx];
+                self.leading_zeros = cp.state.leading_zeros;
+                self.position = cp.index;
+            }
+        }
+
+        while self.position < n {
+            self.get_next()?;
         }
+        Ok(())
     }
 }
 
-impl Decode for Decoder {
+impl<T: Float, S: Source> Decode<T> for Decoder<T, S> {
     fn get_next(&mut self) -> Result<u64, Error> {
         self.get_next()
     }
+
+    // only the first value and the nan end marker of the reference format need get_next's
+    // checks, everything else goes straight from get_value into `out`
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let mut n = 0;
+        while n < out.len() && (self.first || self.format == Format::Reference) {
+            match self.get_next() {
+                Ok(bits) => out[n] = T::from_bits64(bits),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            n += 1;
+        }
+        if self.done {
+            return Ok(n);
+        }
+
+        for val in &mut out[n..] {
+            match self.get_value() {
+                Ok(()) => *val = T::from_bits64(self.curr),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            self.position += 1;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
 }
 
 #[cfg(test)]
 mod chimp_tests {
     use super::{Decoder, Encoder};
-    use crate::bitstream::InputBitStream;
-    use crate::Encode;
+    use crate::bitstream::{Bytes, Error, InputBitStream};
+    use crate::{datasets, reference, Decode, Encode, Format};
+
+    // round trips the values and returns the 2 bit flag of every value after the first
+    fn flags(values: &[f64], window: usize) -> Vec<u64> {
+        let mut encoder = Encoder::with_window(window);
+        let mut starts = Vec::new();
+        for &val in values {
+            starts.push(encoder.w.bits_written());
+            encoder.encode(val);
+        }
+
+        let (bytes, _) = encoder.close();
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes.clone()));
+        let decoded: Vec<u64> = decoder.by_ref().map(|v| v.unwrap().to_bits()).collect();
+        let expected: Vec<u64> = values.iter().map(|v| v.to_bits()).collect();
+        assert_eq!(decoded, expected);
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+
+        let mut r = InputBitStream::new(bytes);
+        starts[1..]
+            .iter()
+            .map(|&bit| {
+                r.seek(bit).unwrap();
+                r.read_bits(2).unwrap()
+            })
+            .collect()
+    }
 
     #[test]
     fn simple_test() {
@@ -283,7 +570,7 @@ mod chimp_tests {
         }
 
         let (bytes, _) = encoder.close();
-        let mut decoder = Decoder::new(InputBitStream::new(bytes));
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
         let mut datapoints = Vec::new();
 
         while let Ok(val) = decoder.get_next() {
@@ -292,4 +579,175 @@ mod chimp_tests {
 
         assert_eq!(datapoints, float_vec);
     }
+
+    #[test]
+    fn window_test() {
+        // noisy pattern repeating every 300 values
+        let float_vec: Vec<f64> = (0..2000)
+            .map(|i| ((i % 300) as f64 * 1.7).sin() * 1000.0 + 2000.0)
+            .collect();
+
+        let mut sizes = Vec::new();
+        for n in [2, 16, 32, 64, 128, 256, 4096] {
+            let mut encoder = Encoder::with_window(n);
+            for &val in &float_vec {
+                encoder.encode(val);
+            }
+
+            let (bytes, len) = encoder.close();
+            sizes.push(len);
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
+            assert_eq!(decoder.decode_to_vec(), Ok(float_vec.clone()));
+        }
+        // only a window bigger than the period finds the repeats
+        assert!(sizes[6] < sizes[4]);
+
+        let mut encoder = Encoder::<f32>::with_window(32);
+        for val in [1.5f32, 2.5, 1.5, 2.5, 3.0] {
+            encoder.encode(val);
+        }
+        let (bytes, _) = encoder.close();
+        let mut decoder: Decoder<f32> = Decoder::new(InputBitStream::new(bytes));
+        assert_eq!(decoder.decode_to_vec(), Ok(vec![1.5, 2.5, 1.5, 2.5, 3.0]));
+
+        // window of 1 isn't valid
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(Box::new([0])));
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_window_test() {
+        Encoder::<f64>::with_window(100);
+    }
+
+    #[test]
+    fn flag_test() {
+        let a = 1.0;
+        // same lsb as a, xor with 40 trailing zeros
+        let b = f64::from_bits(0x3ff0_0100_0000_0000);
+        // new lsb, compared to the previous value with 12 leading zeros
+        let c = f64::from_bits(0x3ff8_0000_0000_0001);
+        let d = f64::from_bits(0x3ff4_0000_0000_0003);
+        assert_eq!(flags(&[a, a, b, c, d], 128), [0, 1, 3, 2]);
+
+        // first xor with 0 leading zeros has to store them, the decoder knows none yet
+        let e = f64::from_bits(0xbff8_0000_0000_0001);
+        let f = f64::from_bits(0x3ff8_0000_0000_0003);
+        assert_eq!(flags(&[a, e, f], 128), [3, 2]);
+        assert_eq!(flags(&[a, c, d], 2), [3, 2]);
+
+        // crossing zero back and forth
+        let float_vec: Vec<f64> = (0..1000).map(|i| (i as f64 * 0.1).sin() * 10.0).collect();
+        for window in [2, 128, 4096] {
+            flags(&float_vec, window);
+        }
+    }
+
+    #[test]
+    fn window_repeat_test() {
+        // distinct lsbs, so repeats can be found through the lookup
+        let value = |i: u64| f64::from_bits(0x4059_0000_0000_0000 + i);
+
+        for window in [2, 16, 128] {
+            let period = window as u64;
+            let values: Vec<f64> = (0..period * 4).map(|i| value(i % period)).collect();
+            let repeats = &flags(&values, window)[window - 1..];
+            assert!(repeats.iter().all(|&flag| flag == 0));
+
+            // one more and every repeat is out of the window
+            let period = window as u64 + 1;
+            let values: Vec<f64> = (0..period * 4).map(|i| value(i % period)).collect();
+            assert!(flags(&values, window).iter().all(|&flag| flag != 0));
+        }
+    }
+
+    #[test]
+    fn lsb_collision_test() {
+        // all share the lsb, each one references the last
+        let values: Vec<f64> = (1..500u64)
+            .map(|i| f64::from_bits(0x4059_0000_0000_0000 ^ (i << 40)))
+            .collect();
+        assert!(flags(&values, 128).iter().all(|&flag| flag == 1));
+
+        // collisions mixed with values that only match the previous one
+        let values: Vec<f64> = (0..2000u64)
+            .map(|i| match i % 3 {
+                0 => f64::from_bits(0x4059_0000_0000_0000 ^ ((i % 256) << 20)),
+                1 => (i as f64).sqrt(),
+                _ => -(i as f64),
+            })
+            .collect();
+        for window in [2, 16, 128, 4096] {
+            flags(&values, window);
+        }
+    }
+
+    #[test]
+    fn datasets_test() {
+        let values = datasets::values();
+        assert!(!values.is_empty());
+
+        for window in [2, 16, 128, 4096] {
+            let mut encoder = Encoder::with_window(window);
+            for &val in &values {
+                encoder.encode(val);
+            }
+            let (bytes, _) = encoder.close();
+            let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
+            for &val in &values {
+                assert_eq!(decoder.get_next(), Ok(val.to_bits()));
+            }
+            assert_eq!(decoder.get_next(), Err(Error::EOF));
+        }
+
+        let values: Vec<f32> = values.iter().map(|&v| v as f32).collect();
+        let mut encoder = Encoder::<f32>::new();
+        encoder.encode_slice(&values);
+        let (bytes, _) = encoder.close();
+        let mut decoder: Decoder<f32> = Decoder::new(InputBitStream::new(bytes));
+        for &val in &values {
+            assert_eq!(decoder.get_next(), Ok(val.to_bits() as u64));
+        }
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    fn reference_test() {
+        let values: Vec<f64> = reference::values().into_iter().map(f64::from_bits).collect();
+        for n in [16, 128] {
+            let mut encoder = Encoder::with_window(n);
+            encoder.format(Format::Reference);
+            for &val in &values {
+                encoder.encode(val);
+            }
+            reference::assert_stream(encoder.close(), &format!("chimpn_{n}.bin"));
+
+            let mut encoder = Encoder::<f64>::with_window(n);
+            encoder.format(Format::Reference);
+            reference::assert_stream(encoder.close(), &format!("chimpn_{n}_empty.bin"));
+
+            // the window isn't in the stream
+            let bytes = reference::fixture(&format!("chimpn_{n}.bin"));
+            let mut decoder: Decoder<f64, _> =
+                Decoder::with_window(InputBitStream::with_source(Bytes::padded(&bytes)), n);
+            decoder.format(Format::Reference);
+            for &val in &values {
+                assert_eq!(decoder.get_next(), Ok(val.to_bits()));
+            }
+            assert_eq!(decoder.get_next(), Err(Error::EOF));
+
+            let bytes = reference::fixture(&format!("chimpn_{n}_empty.bin"));
+            let mut decoder: Decoder<f64, _> =
+                Decoder::with_window(InputBitStream::with_source(Bytes::padded(&bytes)), n);
+            decoder.format(Format::Reference);
+            assert_eq!(decoder.get_next(), Err(Error::EOF));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn reference_f32_test() {
+        Encoder::<f32>::new().format(Format::Reference);
+    }
 }
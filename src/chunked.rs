@@ -0,0 +1,305 @@
+use crate::bitstream::{Error, InputBitStream};
+use crate::block::{invalid_data, Codec};
+use crate::Float;
+use rayon::prelude::*;
+use std::io::{self, Read, Write};
+
+// Values split into independently encoded chunks which are concatenated into one buffer, so
+// encoding and decoding can both run in parallel
+//
+// layout (all integers big endian):
+// | magic "CHNK" | version: u8 | codec: u8 | float bits: u8 | reserved: u8 |
+// | chunk count: u64 | payload words: u64 | (offset: u64, count: u64) per chunk | payload words: u64... |
+pub const MAGIC: [u8; 4] = *b"CHNK";
+pub const VERSION: u8 = 1;
+
+// rayon's automatic chunking doesn't achieve desirable results at times
+pub const DEFAULT_CHUNK_SIZE: usize = 2500;
+
+/// Where a chunk starts in the buffer (in words) and how many values it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunked {
+    pub codec: Codec,
+    pub float_bits: u8,
+    pub index: Vec<Chunk>,
+    pub buffer: Box<[u64]>,
+}
+
+impl Chunked {
+    pub fn encode<T: Float>(codec: Codec, values: &[T]) -> Self {
+        Chunked::with_chunk_size(codec, values, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size<T: Float>(codec: Codec, values: &[T], chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size has to be positive");
+
+        let encoded: Vec<(Box<[u64]>, u64)> = values
+            .par_chunks(chunk_size)
+            .map(|chunk| codec.encode(chunk))
+            .collect();
+
+        // merging is just a copy, every chunk starts on a word boundary
+        let mut index = Vec::with_capacity(encoded.len());
+        let mut buffer = Vec::with_capacity(encoded.iter().map(|(words, _)| words.len()).sum());
+        for ((words, _), chunk) in encoded.iter().zip(values.chunks(chunk_size)) {
+            index.push(Chunk {
+                offset: buffer.len() as u64,
+                count: chunk.len() as u64,
+            });
+            buffer.extend_from_slice(words);
+        }
+
+        Chunked {
+            codec,
+            float_bits: T::BITS as u8,
+            index,
+            buffer: buffer.into_boxed_slice(),
+        }
+    }
+
+    /// number of encoded values, errors if a chunk's count doesn't fit its payload
+    pub fn len(&self) -> Result<usize, Error> {
+        (0..self.index.len()).try_fold(0usize, |len, i| {
+            len.checked_add(self.chunk_len(i)?)
+                .ok_or(Error::InvalidCode)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.iter().all(|chunk| chunk.count == 0)
+    }
+
+    /// size of the compressed payload in bits, without the index
+    pub fn bits(&self) -> u64 {
+        self.buffer.len() as u64 * 64
+    }
+
+    fn chunk_words(&self, i: usize) -> Result<&[u64], Error> {
+        let start = self.index[i].offset;
+        let end = match self.index.get(i + 1) {
+            Some(next) => next.offset,
+            None => self.buffer.len() as u64,
+        };
+        if start > end || end > self.buffer.len() as u64 {
+            return Err(Error::InvalidCode);
+        }
+        Ok(&self.buffer[start as usize..end as usize])
+    }
+
+    fn chunk_len(&self, i: usize) -> Result<usize, Error> {
+        let count = self.index[i].count;
+        let bits = self.chunk_words(i)?.len() as u64 * 64;
+        if count > self.codec.max_count(bits) {
+            return Err(Error::InvalidCode);
+        }
+        usize::try_from(count).map_err(|_| Error::InvalidCode)
+    }
+
+    fn decode_chunk<T: Float>(&self, i: usize, out: &mut [T]) -> Result<(), Error> {
+        let r = InputBitStream::from_slice(self.chunk_words(i)?);
+        let mut decoder = self.codec.decoder::<T, _>(r);
+        // chunk ended before count values were read
+        if decoder.decode_into(out)? < out.len() {
+            return Err(Error::Truncated);
+        }
+        Ok(())
+    }
+
+    /// decodes all chunks in parallel, each straight into its part of `out`
+    ///
+    /// panics if `out` doesn't have room for exactly `len()` values
+    pub fn decode_into<T: Float>(&self, out: &mut [T]) -> Result<(), Error> {
+        assert_eq!(out.len(), self.len()?, "output has to fit all values");
+        if self.float_bits as u32 != T::BITS {
+            return Err(Error::InvalidCode);
+        }
+
+        let mut slices = Vec::with_capacity(self.index.len());
+        let mut rest = out;
+        for chunk in &self.index {
+            let (head, tail) = rest.split_at_mut(chunk.count as usize);
+            slices.push(head);
+            rest = tail;
+        }
+
+        slices
+            .into_par_iter()
+            .enumerate()
+            .try_for_each(|(i, out)| self.decode_chunk(i, out))
+    }
+
+    pub fn decode<T: Float>(&self) -> Result<Vec<T>, Error> {
+        let mut values = vec![T::from_bits64(0); self.len()?];
+        self.decode_into(&mut values)?;
+        Ok(values)
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&[VERSION, self.codec as u8, self.float_bits, 0])?;
+        w.write_all(&(self.index.len() as u64).to_be_bytes())?;
+        w.write_all(&(self.buffer.len() as u64).to_be_bytes())?;
+        for chunk in &self.index {
+            w.write_all(&chunk.offset.to_be_bytes())?;
+            w.write_all(&chunk.count.to_be_bytes())?;
+        }
+        for word in self.buffer.iter() {
+            w.write_all(&word.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut r: impl Read) -> io::Result<Self> {
+        let mut fixed = [0u8; 24];
+        r.read_exact(&mut fixed)?;
+
+        if fixed[..4] != MAGIC {
+            return Err(invalid_data("not a chunked chimp buffer"));
+        }
+        if fixed[4] != VERSION {
+            return Err(invalid_data("unsupported version"));
+        }
+        let codec = Codec::from_tag(fixed[5]).ok_or_else(|| invalid_data("unknown codec"))?;
+        let float_bits = fixed[6];
+        if float_bits != 32 && float_bits != 64 {
+            return Err(invalid_data("unsupported float width"));
+        }
+        let chunks = u64::from_be_bytes(fixed[8..16].try_into().unwrap());
+        let words = u64::from_be_bytes(fixed[16..24].try_into().unwrap());
+
+        // no preallocation, the counts aren't trusted until the data is actually there
+        let mut word = [0u8; 8];
+        let mut read_word = || -> io::Result<u64> {
+            r.read_exact(&mut word)?;
+            Ok(u64::from_be_bytes(word))
+        };
+
+        let mut index = Vec::new();
+        for _ in 0..chunks {
+            let offset = read_word()?;
+            let count = read_word()?;
+            if index.last().is_some_and(|prev: &Chunk| prev.offset > offset) || offset > words {
+                return Err(invalid_data("chunk offsets out of order"));
+            }
+            index.push(Chunk { offset, count });
+        }
+
+        let mut buffer = Vec::new();
+        for _ in 0..words {
+            buffer.push(read_word()?);
+        }
+
+        let chunked = Chunked {
+            codec,
+            float_bits,
+            index,
+            buffer: buffer.into_boxed_slice(),
+        };
+        if chunked.len().is_err() {
+            return Err(invalid_data("chunk count doesn't fit its payload"));
+        }
+        Ok(chunked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, Chunked};
+    use crate::bitstream::Error;
+    use crate::block::Codec;
+
+    const CODECS: [Codec; 5] = [
+        Codec::Chimp,
+        Codec::ChimpN,
+        Codec::Gorilla,
+        Codec::Aligned,
+        Codec::Decimal,
+    ];
+
+    #[test]
+    fn round_trip_test() {
+        let float_vec: Vec<f64> = (0..10_000)
+            .map(|i| (i as f64 * 0.01).sin() * 10.0 + 100.0)
+            .collect();
+
+        for codec in CODECS {
+            for chunk_size in [1, 7, 2500, 10_000, 20_000] {
+                let chunked = Chunked::with_chunk_size(codec, &float_vec, chunk_size);
+                assert_eq!(chunked.len(), Ok(float_vec.len()));
+                assert_eq!(chunked.index.len(), float_vec.len().div_ceil(chunk_size));
+                assert_eq!(chunked.decode(), Ok(float_vec.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn f32_test() {
+        let float_vec: Vec<f32> = (0..5000).map(|i| i as f32 / 8.0).collect();
+
+        for codec in CODECS {
+            let chunked = Chunked::with_chunk_size(codec, &float_vec, 333);
+            let mut out = vec![0f32; float_vec.len()];
+            chunked.decode_into(&mut out).unwrap();
+            assert_eq!(out, float_vec);
+            assert_eq!(chunked.decode::<f64>(), Err(Error::InvalidCode));
+        }
+    }
+
+    #[test]
+    fn empty_test() {
+        let chunked = Chunked::encode::<f64>(Codec::Chimp, &[]);
+        assert!(chunked.is_empty());
+        assert_eq!(chunked.decode::<f64>(), Ok(vec![]));
+    }
+
+    #[test]
+    fn write_read_test() {
+        let float_vec: Vec<f64> = (0..3000).map(|i| (i % 100) as f64 * 1.5).collect();
+        let chunked = Chunked::with_chunk_size(Codec::Gorilla, &float_vec, 1000);
+
+        let mut bytes = Vec::new();
+        chunked.write_to(&mut bytes).unwrap();
+        let read = Chunked::read_from(&bytes[..]).unwrap();
+        assert_eq!(read, chunked);
+        assert_eq!(read.decode(), Ok(float_vec));
+
+        assert!(Chunked::read_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn invalid_test() {
+        let float_vec: Vec<f64> = (0..3000).map(|i| i as f64).collect();
+        let chunked = Chunked::with_chunk_size(Codec::Chimp, &float_vec, 1000);
+
+        // index pointing past the buffer
+        let mut corrupt = chunked.clone();
+        corrupt.index[1].offset = corrupt.buffer.len() as u64 + 1;
+        assert_eq!(corrupt.decode::<f64>(), Err(Error::InvalidCode));
+
+        // more values than the chunk holds
+        let mut corrupt = chunked.clone();
+        corrupt.index[0] = Chunk {
+            offset: 0,
+            count: 1001,
+        };
+        assert_eq!(corrupt.decode::<f64>(), Err(Error::Truncated));
+
+        // counts no payload could hold, which used to overflow the output allocation
+        for count in [1 << 62, u64::MAX] {
+            let mut corrupt = chunked.clone();
+            corrupt.index[0].count = count;
+            assert_eq!(corrupt.len(), Err(Error::InvalidCode));
+            assert_eq!(corrupt.decode::<f64>(), Err(Error::InvalidCode));
+
+            let mut bytes = Vec::new();
+            corrupt.write_to(&mut bytes).unwrap();
+            assert!(Chunked::read_from(&bytes[..]).is_err());
+        }
+    }
+}
@@ -0,0 +1,611 @@
+use crate::bitstream::{Source, Words};
+use crate::*;
+use std::marker::PhantomData;
+
+// Decimal preprocessing in front of chimp, along the lines of ALP and Elf
+//
+// values like 48.8 have an endless mantissa, but they're just 488 with a decimal exponent of 1.
+// the encoder collects blocks of BLOCK_LEN values, picks the exponent that turns the most of
+// them into integers that come back out bit for bit, and stores those frame-of-reference
+// bitpacked. everything else (NaN, -0.0, values with too many digits, ...) is an exception
+// that goes through a chimp encoder. every block looks like this:
+// | 1 | length - 1: 10 | exponent: 5 | min: 64 | width: 7 | int - min: width per value |
+// | exceptions: 11 | position: 10 per exception | chimp stream of the exceptions |
+// and the stream ends with a 0 bit. blocks that wouldn't get any smaller that way are only a
+// chimp stream, marked with the exponent RAW_BLOCK:
+// | 1 | length - 1: 10 | RAW_BLOCK: 5 | chimp stream of all values |
+// chimp streams are stored as | bits: 32 | bits... |, so each block can be decoded on its own
+
+const LEN_BITS: u32 = 10;
+pub const BLOCK_LEN: usize = 1 << LEN_BITS;
+
+const EXP_BITS: u32 = 5;
+const RAW_BLOCK: u64 = (1 << EXP_BITS) - 1;
+const WIDTH_BITS: u32 = 7;
+const CHIMP_LEN_BITS: u32 = 32;
+
+pub const MAX_EXPONENT: u32 = 18;
+const POW10: [f64; MAX_EXPONENT as usize + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18,
+];
+
+// integers above this aren't exact as f64 anymore
+const MAX_INT: f64 = (1u64 << 53) as f64;
+
+#[inline(always)]
+fn from_int<T: Float>(int: i64, exp: u32) -> T {
+    T::from_f64(int as f64 / POW10[exp as usize])
+}
+
+// the integer `value` turns into with exponent `exp`, if it decodes to the exact same bits
+#[inline(always)]
+fn to_int<T: Float>(value: T, exp: u32) -> Option<i64> {
+    let scaled = (value.to_f64() * POW10[exp as usize]).round();
+    // also catches NaN and infinities
+    if scaled.is_nan() || scaled.abs() > MAX_INT {
+        return None;
+    }
+    let int = scaled as i64;
+    (from_int::<T>(int, exp).to_bits64() == value.to_bits64()).then_some(int)
+}
+
+// estimated bits for a block with exponent `exp`, exceptions are counted as uncompressed
+fn cost<T: Float>(values: &[T], exp: u32) -> u64 {
+    let (mut min, mut max, mut ints) = (i64::MAX, i64::MIN, 0);
+    for int in values.iter().filter_map(|&v| to_int(v, exp)) {
+        min = min.min(int);
+        max = max.max(int);
+        ints += 1;
+    }
+    let width = match ints {
+        0 => 0,
+        _ => 64 - (max.wrapping_sub(min) as u64).leading_zeros() as u64,
+    };
+    let exceptions = values.len() as u64 - ints;
+    values.len() as u64 * width + exceptions * (LEN_BITS + T::BITS) as u64
+}
+
+// cheapest exponent, None if plain chimp is cheaper. smaller exponents win ties and once every
+// value is an integer, larger ones only make them wider
+fn best_exponent<T: Float>(values: &[T]) -> Option<u32> {
+    let mut best = (None, values.len() as u64 * T::BITS as u64);
+    for exp in 0..=MAX_EXPONENT {
+        let cost = cost(values, exp);
+        if cost < best.1 {
+            best = (Some(exp), cost);
+        }
+        if values.iter().all(|&v| to_int(v, exp).is_some()) {
+            break;
+        }
+    }
+    best.0
+}
+
+// chimp stream can't be longer than this for `count` values, so garbage lengths are caught
+// before anything gets allocated
+#[inline(always)]
+fn max_chimp_bits<T: Float>(count: usize) -> u64 {
+    (count as u64 + 1) * (T::BITS as u64 + 16)
+}
+
+#[derive(Debug)]
+pub struct Encoder<T: Float = f64, S: Sink = Vec<u64>> {
+    first: bool,
+    w: OutputBitStream<S>,
+    block: Vec<T>,
+}
+
+impl<T: Float> Encoder<T> {
+    pub fn new() -> Self {
+        Encoder::with_sink(Vec::new())
+    }
+
+    pub fn with_capacity(capa: usize) -> Self {
+        Encoder::with_sink(Vec::with_capacity(capa))
+    }
+}
+
+impl<T: Float> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
+        Encoder {
+            first: true,
+            w: OutputBitStream::with_sink(sink),
+            block: Vec::with_capacity(BLOCK_LEN),
+        }
+    }
+
+    fn write_chimp(&mut self, values: &[T]) {
+        let mut chimp = chimp::Encoder::<T>::with_capacity(values.len());
+        for &val in values {
+            chimp.encode(val);
+        }
+        let (words, len) = chimp.close();
+
+        self.w.write_bits(len, CHIMP_LEN_BITS);
+        let mut left = len;
+        for word in words.iter() {
+            let bits = left.min(64) as u32;
+            self.w.write_bits(word >> (64 - bits), bits);
+            left -= bits as u64;
+        }
+    }
+
+    fn write_block(&mut self) {
+        let values = std::mem::take(&mut self.block);
+        self.w.write_bit(1);
+        self.w.write_bits(values.len() as u64 - 1, LEN_BITS);
+
+        if let Some(exp) = best_exponent(&values) {
+            let ints: Vec<Option<i64>> = values.iter().map(|&v| to_int(v, exp)).collect();
+            let exceptions: Vec<usize> =
+                (0..ints.len()).filter(|&i| ints[i].is_none()).collect();
+
+            let min = ints.iter().flatten().min().copied().unwrap();
+            let max = ints.iter().flatten().max().copied().unwrap();
+            let width = 64 - (max.wrapping_sub(min) as u64).leading_zeros();
+
+            self.w.write_bits(exp as u64, EXP_BITS);
+            self.w.write_bits(min as u64, 64);
+            self.w.write_bits(width as u64, WIDTH_BITS);
+            // exceptions are stored as min, so they don't make the others any wider
+            for int in &ints {
+                self.w.write_bits(int.unwrap_or(min).wrapping_sub(min) as u64, width);
+            }
+
+            self.w.write_bits(exceptions.len() as u64, LEN_BITS + 1);
+            for &i in &exceptions {
+                self.w.write_bits(i as u64, LEN_BITS);
+            }
+            if !exceptions.is_empty() {
+                let rest: Vec<T> = exceptions.iter().map(|&i| values[i]).collect();
+                self.write_chimp(&rest);
+            }
+        } else {
+            self.w.write_bits(RAW_BLOCK, EXP_BITS);
+            self.write_chimp(&values);
+        }
+
+        // keeps the allocation around for the next block
+        self.block = values;
+        self.block.clear();
+    }
+}
+
+impl<T: Float, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
+
+    fn encode(&mut self, value: T) {
+        self.first = false;
+        self.block.push(value);
+        if self.block.len() == BLOCK_LEN {
+            self.write_block();
+        }
+    }
+
+    fn close(self) -> (S::Output, u64) {
+        let mut this = self;
+        // empty stream stays empty
+        if !this.first {
+            if !this.block.is_empty() {
+                this.write_block();
+            }
+            this.w.write_bit(0);
+        }
+        this.w.close()
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<T: Float = f64, S: Source = Words> {
+    first: bool,
+    done: bool,
+    r: InputBitStream<S>,
+    values: Vec<u64>, // current block
+    next: usize,
+    float: PhantomData<T>,
+}
+
+impl<T: Float> Decoder<T> {
+    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
+        Decoder::new(InputBitStream::new(buffer))
+    }
+}
+
+impl<T: Float, S: Source> Decoder<T, S> {
+    pub fn new(r: InputBitStream<S>) -> Self {
+        Decoder {
+            first: true,
+            done: false,
+            r,
+            values: Vec::with_capacity(BLOCK_LEN),
+            next: 0,
+            float: PhantomData,
+        }
+    }
+
+    // exactly `count` values from an embedded chimp stream
+    fn read_chimp(&mut self, count: usize) -> Result<Vec<u64>, Error> {
+        let len = self.r.read_bits(CHIMP_LEN_BITS)?;
+        if len > max_chimp_bits::<T>(count) {
+            return Err(Error::InvalidCode);
+        }
+
+        let mut words = Vec::with_capacity(len.div_ceil(64) as usize);
+        let mut left = len;
+        while left > 0 {
+            let bits = left.min(64) as u32;
+            words.push(self.r.read_bits(bits)? << (64 - bits));
+            left -= bits as u64;
+        }
+
+        let mut chimp: chimp::Decoder<T> = chimp::Decoder::from_buffer(words.into());
+        (0..count)
+            .map(|_| match chimp.get_next() {
+                // the block says there are more values than the chimp stream has
+                Err(Error::EOF | Error::Truncated) => Err(Error::InvalidCode),
+                res => res,
+            })
+            .collect()
+    }
+
+    fn read_block(&mut self) -> Result<(), Error> {
+        if self.first {
+            self.first = false;
+            // nothing was encoded
+            if self.r.is_empty()? {
+                self.done = true;
+                return Err(Error::EOF);
+            }
+        }
+        // flag, length and exponent all in one go
+        let header_len = 1 + LEN_BITS + EXP_BITS;
+        let header = self.r.peek_bits(header_len);
+        if header >> (header_len - 1) == 0 {
+            self.r.consume(1)?;
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        self.r.consume(header_len)?;
+        let len = ((header >> EXP_BITS) & ((1 << LEN_BITS) - 1)) as usize + 1;
+        let exp = header & ((1 << EXP_BITS) - 1);
+        self.values.clear();
+        self.next = 0;
+
+        if exp == RAW_BLOCK {
+            self.values = self.read_chimp(len)?;
+            return Ok(());
+        }
+        if exp > MAX_EXPONENT as u64 {
+            return Err(Error::InvalidCode);
+        }
+
+        let min = self.r.read_bits(64)? as i64;
+        let width = self.r.read_bits(WIDTH_BITS)? as u32;
+        if width > 64 {
+            return Err(Error::InvalidCode);
+        }
+        for _ in 0..len {
+            let int = min.wrapping_add(self.r.read_bits(width)? as i64);
+            self.values.push(from_int::<T>(int, exp as u32).to_bits64());
+        }
+
+        let exceptions = self.r.read_bits(LEN_BITS + 1)? as usize;
+        if exceptions > len {
+            return Err(Error::InvalidCode);
+        }
+        let mut positions = Vec::with_capacity(exceptions);
+        for _ in 0..exceptions {
+            let i = self.r.read_bits(LEN_BITS)? as usize;
+            if i >= len {
+                return Err(Error::InvalidCode);
+            }
+            positions.push(i);
+        }
+        if exceptions > 0 {
+            for (i, val) in positions.into_iter().zip(self.read_chimp(exceptions)?) {
+                self.values[i] = val;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_next(&mut self) -> Result<u64, Error> {
+        if self.done {
+            return Err(Error::EOF);
+        }
+        if self.next == self.values.len() {
+            if let Err(err) = self.read_block() {
+                // don't hand out the rest of a broken block on the next call
+                self.values.clear();
+                self.next = 0;
+                return Err(err);
+            }
+        }
+
+        let val = self.values[self.next];
+        self.next += 1;
+        Ok(val)
+    }
+}
+
+impl<T: Float, S: Source> Decode<T> for Decoder<T, S> {
+    fn get_next(&mut self) -> Result<u64, Error> {
+        self.get_next()
+    }
+
+    // copies the decoded blocks over as a whole
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let mut n = 0;
+        while n < out.len() && !self.done {
+            if self.next == self.values.len() {
+                match self.read_block() {
+                    Ok(()) => {}
+                    Err(Error::EOF) => break,
+                    Err(err) => {
+                        // same as get_next, the rest of a broken block is gone
+                        self.values.clear();
+                        self.next = 0;
+                        return Err(err);
+                    }
+                }
+            }
+
+            let values = &self.values[self.next..];
+            let len = values.len().min(out.len() - n);
+            for (val, &bits) in out[n..n + len].iter_mut().zip(values) {
+                *val = T::from_bits64(bits);
+            }
+            self.next += len;
+            n += len;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_exponent, Decoder, Encoder, BLOCK_LEN, RAW_BLOCK};
+    use crate::bitstream::{Error, InputBitStream, OutputBitStream};
+    use crate::{chimp, Decode, Encode, Float};
+
+    fn round_trip<T: Float>(values: &[T]) -> u64 {
+        let mut encoder = Encoder::<T>::new();
+        for &val in values {
+            encoder.encode(val);
+        }
+        let (words, len) = encoder.close();
+
+        let decoded: Vec<u64> = Decoder::<T>::from_buffer(words)
+            .map(|val| val.unwrap().to_bits64())
+            .collect();
+        let expected: Vec<u64> = values.iter().map(|v| v.to_bits64()).collect();
+        assert_eq!(decoded, expected);
+        len
+    }
+
+    fn chimp_len<T: Float>(values: &[T]) -> u64 {
+        let mut encoder = chimp::Encoder::<T>::new();
+        for &val in values {
+            encoder.encode(val);
+        }
+        encoder.close().1
+    }
+
+    // (exponent, width) of the first block
+    fn header(values: &[f64]) -> (u64, u64) {
+        let mut encoder = Encoder::new();
+        for &val in values {
+            encoder.encode(val);
+        }
+        let (words, _) = encoder.close();
+        let mut r = InputBitStream::new(words);
+        assert_eq!(r.read_bits(1), Ok(1));
+        assert_eq!(r.read_bits(10), Ok(values.len() as u64 - 1));
+        let exp = r.read_bits(5).unwrap();
+        if exp == RAW_BLOCK {
+            return (exp, 0);
+        }
+        r.read_bits(64).unwrap();
+        (exp, r.read_bits(7).unwrap())
+    }
+
+    #[test]
+    fn simple_test() {
+        let float_vec: Vec<f64> = [
+            49.4, 48.8, 46.4, 47.9, 48.7, 48.9, 48.8, 46.4, 47.9, 48.7, 48.9, -12.5, 0.0, 100.0,
+        ]
+        .to_vec();
+        round_trip(&float_vec);
+
+        let mut encoder = Encoder::new();
+        for val in &float_vec {
+            encoder.encode(*val);
+        }
+        let (words, _) = encoder.close();
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(words));
+        assert_eq!(decoder.decode_to_vec(), Ok(float_vec));
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    fn exponent_test() {
+        assert_eq!(best_exponent(&[1.0, 2.0, 3.0]), Some(0));
+        assert_eq!(best_exponent(&[48.8, 49.4, 1.0]), Some(1));
+        assert_eq!(best_exponent(&[0.125, 1.5]), Some(3));
+        assert_eq!(best_exponent(&[0.001f32, 7.25]), Some(3));
+        // 0.1 + 0.2 is 0.30000000000000004, too many digits for an exact integer
+        assert_eq!(best_exponent(&[0.1 + 0.2, 0.5, 0.7]), Some(1));
+        assert_eq!(best_exponent(&[f64::NAN]), None);
+
+        // 488 - 464 = 24 fits in 5 bits
+        assert_eq!(header(&[48.8, 46.4, 47.9, 48.7]), (1, 5));
+        // repeats need 0 bits
+        assert_eq!(header(&[21.5; 10]), (1, 0));
+        // 10 digits after the point still beats 64 bit floats
+        let values: Vec<f64> = (1..100)
+            .map(|i| (i as f64 / 7.0 * 1e10).round() / 1e10)
+            .collect();
+        assert_eq!(header(&values), (10, 38));
+        // all exceptions
+        let values: Vec<f64> = (1..100).map(|i| (i as f64).sin() * 1e-30).collect();
+        assert_eq!(header(&values), (RAW_BLOCK, 0));
+    }
+
+    #[test]
+    fn exception_test() {
+        let mut values: Vec<f64> = (0..3000).map(|i| (i % 700) as f64 * 0.01 - 3.5).collect();
+        let specials = [
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_beef),
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            -0.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            std::f64::consts::PI,
+            1e300,
+            9007199254740993.0,
+        ];
+        for (i, &special) in specials.iter().enumerate() {
+            values[i * 250] = special;
+            values[i * 250 + 1] = special;
+        }
+        round_trip(&values);
+
+        // every value is an exception except one
+        let mut values: Vec<f64> = (0..BLOCK_LEN).map(|i| (i as f64).sin()).collect();
+        values[17] = 4.0;
+        round_trip(&values);
+    }
+
+    #[test]
+    fn blocks_test() {
+        // block sizes around the boundaries, every block with a different exponent
+        for len in [1, 2, BLOCK_LEN - 1, BLOCK_LEN, BLOCK_LEN + 1, 3 * BLOCK_LEN + 17] {
+            let values: Vec<f64> = (0..len)
+                .map(|i| (i % 97) as f64 / [1.0, 10.0, 100.0, 1000.0][i / BLOCK_LEN % 4])
+                .collect();
+            round_trip(&values);
+        }
+    }
+
+    #[test]
+    fn empty_test() {
+        let (words, len) = Encoder::<f64>::new().close();
+        assert_eq!(len, 0);
+        let mut decoder: Decoder = Decoder::from_buffer(words);
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    fn f32_test() {
+        let values: Vec<f32> = (0..2000).map(|i| ((i % 300) as f32 - 100.0) / 10.0).collect();
+        let len = round_trip(&values);
+        assert!(len < chimp_len(&values) / 2);
+
+        round_trip(&[f32::NAN, -0.0, 1.5, f32::MAX, 48.8, 3.4e38, 1e-40]);
+    }
+
+    // decodes a hand written block of 3 values with exponent 1
+    fn decode_block(rest: impl FnOnce(&mut OutputBitStream)) -> Result<Vec<f64>, Error> {
+        let mut w = OutputBitStream::new();
+        w.write_bits(1, 1);
+        w.write_bits(2, 10);
+        w.write_bits(1, 5);
+        w.write_bits(464, 64);
+        w.write_bits(5, 7);
+        w.write_bits(24 << 10, 15);
+        rest(&mut w);
+        let (words, _) = w.close();
+        Decoder::from_buffer(words).decode_to_vec()
+    }
+
+    #[test]
+    fn invalid_test() {
+        let no_exceptions = |w: &mut OutputBitStream| {
+            w.write_bits(0, 11);
+            w.write_bits(0, 1);
+        };
+        assert_eq!(decode_block(no_exceptions), Ok(vec![48.8, 46.4, 46.4]));
+
+        // more exceptions than values
+        assert_eq!(decode_block(|w| w.write_bits(4, 11)), Err(Error::InvalidCode));
+        // position past the end of the block
+        let position = |w: &mut OutputBitStream| {
+            w.write_bits(1, 11);
+            w.write_bits(3, 10);
+        };
+        assert_eq!(decode_block(position), Err(Error::InvalidCode));
+        // chimp stream that's way too long for one value
+        let chimp_len = |w: &mut OutputBitStream| {
+            w.write_bits(1, 11);
+            w.write_bits(2, 10);
+            w.write_bits(u32::MAX as u64, 32);
+        };
+        assert_eq!(decode_block(chimp_len), Err(Error::InvalidCode));
+        // empty chimp stream
+        let empty = |w: &mut OutputBitStream| {
+            w.write_bits(1, 11);
+            w.write_bits(2, 10);
+            w.write_bits(0, 32);
+        };
+        assert_eq!(decode_block(empty), Err(Error::InvalidCode));
+
+        // exponent 19 doesn't exist
+        let mut w = OutputBitStream::new();
+        w.write_bits(1, 1);
+        w.write_bits(0, 10);
+        w.write_bits(19, 5);
+        let mut decoder: Decoder = Decoder::from_buffer(w.close().0);
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+    }
+
+    #[test]
+    fn truncated_test() {
+        let values: Vec<f64> = (0..300).map(|i| i as f64 * 0.5).chain([f64::NAN]).collect();
+        let mut encoder = Encoder::new();
+        for &val in &values {
+            encoder.encode(val);
+        }
+        let (words, _) = encoder.close();
+
+        for end in 0..words.len() {
+            let mut decoder: Decoder<f64, _> =
+                Decoder::new(InputBitStream::from_slice(&words[..end]));
+            let res = decoder.decode_to_vec();
+            // an empty buffer is indistinguishable from an empty stream
+            if end == 0 {
+                assert_eq!(res, Ok(vec![]));
+            } else {
+                assert_eq!(res, Err(Error::Truncated));
+            }
+        }
+    }
+
+    #[test]
+    fn datasets_test() {
+        let values = crate::datasets::values();
+        assert!(!values.is_empty());
+
+        let len = round_trip(&values);
+        assert!(len < chimp_len(&values), "{len} vs {}", chimp_len(&values));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::bitstream::{Error, InputBitStream, OutputBitStream, Sink, Source, Words};
+use crate::{Decode, Encode, Value};
+use std::marker::PhantomData;
+
+// Delta + zigzag + bitpacking for integer columns like counters
+//
+// the first value is stored as is, every value after that as the zigzag encoded difference to
+// the one before. differences are collected in frames of up to FRAME_LEN and bitpacked with the
+// width of the largest one, so a frame of small steps only takes a few bits per value:
+// | first: 64 | frame... | 0 |
+// frame: | 1 | length - 1: 7 | width: 7 | zigzag deltas: width each |
+// differences wrap around, so u64 ids and i64 values jumping from MIN to MAX work as well
+
+const LEN_BITS: u32 = 7;
+pub const FRAME_LEN: usize = 1 << LEN_BITS;
+const WIDTH_BITS: u32 = 7;
+
+// small negative deltas become small numbers too: 0, -1, 1, -2, 2, ... -> 0, 1, 2, 3, 4, ...
+#[inline(always)]
+fn zigzag(delta: u64) -> u64 {
+    (delta << 1) ^ ((delta as i64 >> 63) as u64)
+}
+
+#[inline(always)]
+fn unzigzag(zigzag: u64) -> u64 {
+    (zigzag >> 1) ^ (zigzag & 1).wrapping_neg()
+}
+
+#[derive(Debug)]
+pub struct Encoder<T: Value = i64, S: Sink = Vec<u64>> {
+    first: bool,
+    curr: u64,
+    w: OutputBitStream<S>,
+    frame: Vec<u64>, // zigzag deltas that aren't written yet
+    value: PhantomData<T>,
+}
+
+impl<T: Value> Encoder<T> {
+    pub fn new() -> Self {
+        Encoder::with_sink(Vec::new())
+    }
+
+    pub fn with_capacity(capa: usize) -> Self {
+        Encoder::with_sink(Vec::with_capacity(capa))
+    }
+}
+
+impl<T: Value> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Value, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
+        Encoder {
+            first: true,
+            curr: 0,
+            w: OutputBitStream::with_sink(sink),
+            frame: Vec::with_capacity(FRAME_LEN),
+            value: PhantomData,
+        }
+    }
+
+    fn write_frame(&mut self) {
+        let max = self.frame.iter().copied().max().unwrap_or(0);
+        let width = 64 - max.leading_zeros();
+
+        self.w.write_bit(1);
+        self.w.write_bits(self.frame.len() as u64 - 1, LEN_BITS);
+        self.w.write_bits(width as u64, WIDTH_BITS);
+        for &zigzag in &self.frame {
+            self.w.write_bits(zigzag, width);
+        }
+        self.frame.clear();
+    }
+}
+
+impl<T: Value, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
+
+    fn encode(&mut self, value: T) {
+        let value = value.to_bits64();
+        if self.first {
+            self.first = false;
+            self.w.write_bits(value, T::BITS);
+        } else {
+            self.frame.push(zigzag(value.wrapping_sub(self.curr)));
+            if self.frame.len() == FRAME_LEN {
+                self.write_frame();
+            }
+        }
+        self.curr = value;
+    }
+
+    fn close(self) -> (S::Output, u64) {
+        let mut this = self;
+        // empty stream stays empty
+        if !this.first {
+            if !this.frame.is_empty() {
+                this.write_frame();
+            }
+            this.w.write_bit(0);
+        }
+        this.w.close()
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<T: Value = i64, S: Source = Words> {
+    first: bool,
+    done: bool,
+    curr: u64,
+    r: InputBitStream<S>,
+    left: u64, // values left in the current frame
+    width: u32,
+    value: PhantomData<T>,
+}
+
+impl<T: Value> Decoder<T> {
+    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
+        Decoder::new(InputBitStream::new(buffer))
+    }
+}
+
+impl<T: Value, S: Source> Decoder<T, S> {
+    pub fn new(r: InputBitStream<S>) -> Self {
+        Decoder {
+            first: true,
+            done: false,
+            curr: 0,
+            r,
+            left: 0,
+            width: 0,
+            value: PhantomData,
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<(), Error> {
+        // flag, length and width all in one go
+        let header_len = 1 + LEN_BITS + WIDTH_BITS;
+        let header = self.r.peek_bits(header_len);
+        if header >> (header_len - 1) == 0 {
+            self.r.consume(1)?;
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        self.r.consume(header_len)?;
+        let left = ((header >> WIDTH_BITS) & ((1 << LEN_BITS) - 1)) + 1;
+        let width = (header & ((1 << WIDTH_BITS) - 1)) as u32;
+        if width > 64 {
+            return Err(Error::InvalidCode);
+        }
+        self.left = left;
+        self.width = width;
+        Ok(())
+    }
+
+    pub fn get_next(&mut self) -> Result<u64, Error> {
+        if self.done {
+            return Err(Error::EOF);
+        }
+
+        if self.first {
+            self.first = false;
+            // nothing was encoded
+            if self.r.is_empty()? {
+                self.done = true;
+                return Err(Error::EOF);
+            }
+            self.curr = self.r.read_bits(T::BITS)?;
+            return Ok(self.curr);
+        }
+
+        if self.left == 0 {
+            self.read_frame()?;
+        }
+        let delta = unzigzag(self.r.read_bits(self.width)?);
+        self.left -= 1;
+        self.curr = self.curr.wrapping_add(delta);
+        Ok(self.curr)
+    }
+}
+
+impl<T: Value, S: Source> Decode<T> for Decoder<T, S> {
+    fn get_next(&mut self) -> Result<u64, Error> {
+        self.get_next()
+    }
+
+    // the first value goes through get_next, after that a frame at a time
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        let mut n = 0;
+        if self.first && !out.is_empty() {
+            match self.get_next() {
+                Ok(bits) => out[0] = T::from_bits64(bits),
+                Err(Error::EOF) => return Ok(0),
+                Err(err) => return Err(err),
+            }
+            n = 1;
+        }
+
+        while n < out.len() && !self.done {
+            if self.left == 0 {
+                match self.read_frame() {
+                    Ok(()) => {}
+                    Err(Error::EOF) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            let len = self.left.min((out.len() - n) as u64) as usize;
+            for val in &mut out[n..n + len] {
+                let delta = unzigzag(self.r.read_bits(self.width)?);
+                self.left -= 1;
+                self.curr = self.curr.wrapping_add(delta);
+                *val = T::from_bits64(self.curr);
+            }
+            n += len;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Value, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unzigzag, zigzag, Decoder, Encoder, FRAME_LEN};
+    use crate::bitstream::{Error, InputBitStream, OutputBitStream};
+    use crate::{Decode, Encode, Value};
+
+    fn round_trip<T: Value>(values: &[T]) -> u64 {
+        let mut encoder = Encoder::<T>::new();
+        for &val in values {
+            encoder.encode(val);
+        }
+        let (words, len) = encoder.close();
+
+        let mut decoder = Decoder::<T>::from_buffer(words);
+        assert_eq!(decoder.decode_to_vec(), Ok(values.to_vec()));
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+        len
+    }
+
+    #[test]
+    fn zigzag_test() {
+        let pairs = [
+            (0, 0),
+            (-1, 1),
+            (1, 2),
+            (-2, 3),
+            (i64::MAX, u64::MAX - 1),
+            (i64::MIN, u64::MAX),
+        ];
+        for (delta, expected) in pairs {
+            assert_eq!(zigzag(delta as u64), expected);
+            assert_eq!(unzigzag(expected), delta as u64);
+        }
+    }
+
+    #[test]
+    fn simple_test() {
+        let counter: Vec<i64> = (0..1000).map(|i| 1_000_000 + i * 3 + i % 5).collect();
+        let len = round_trip(&counter);
+        // 4 bits per value, plus the frame headers
+        assert!(len < 1000 * 5, "{len}");
+
+        round_trip(&[-5i64, 3, -5, 0, 0, 0, 17]);
+        round_trip(&[i64::MIN, i64::MAX, i64::MIN, 0, -1, i64::MAX]);
+        round_trip(&[42i64]);
+    }
+
+    #[test]
+    fn u64_test() {
+        let ids: Vec<u64> = (0..500).map(|i| u64::MAX - 1000 + i * 2).collect();
+        round_trip(&ids);
+        round_trip(&[u64::MAX, 0, u64::MAX, 1 << 63, 0]);
+    }
+
+    #[test]
+    fn frames_test() {
+        for len in [FRAME_LEN, FRAME_LEN + 1, FRAME_LEN + 2, 5 * FRAME_LEN + 3] {
+            // every frame needs a different width
+            let values: Vec<i64> = (0..len as i64).map(|i| i * i * (i / 100 + 1)).collect();
+            round_trip(&values);
+        }
+
+        // a constant series only has the frame headers, which fit in the word after the first
+        let len = round_trip(&[7i64; 1 + 2 * FRAME_LEN]);
+        assert_eq!(len, 128);
+    }
+
+    #[test]
+    fn empty_test() {
+        let (words, len) = Encoder::<i64>::new().close();
+        assert_eq!(len, 0);
+        let mut decoder: Decoder = Decoder::from_buffer(words);
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
+
+    #[test]
+    fn invalid_test() {
+        let mut w = OutputBitStream::new();
+        w.write_bits(1, 64);
+        w.write_bits(1, 1);
+        w.write_bits(0, 7);
+        w.write_bits(65, 7);
+        let mut decoder: Decoder = Decoder::from_buffer(w.close().0);
+        assert_eq!(decoder.get_next(), Ok(1));
+        assert_eq!(decoder.get_next(), Err(Error::InvalidCode));
+
+        let values: Vec<i64> = (0..300).map(|i| i * 1001).collect();
+        let mut encoder = Encoder::new();
+        for &val in &values {
+            encoder.encode(val);
+        }
+        let (words, _) = encoder.close();
+        for end in 1..words.len() {
+            let mut decoder: Decoder<i64, _> =
+                Decoder::new(InputBitStream::from_slice(&words[..end]));
+            assert_eq!(decoder.decode_to_vec(), Err(Error::Truncated));
+        }
+    }
+}
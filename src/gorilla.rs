@@ -1,39 +1,119 @@
-use crate::bitstream::{Error, InputBitStream, OutputBitStream};
-use crate::{Bit, Decode, Encode, NAN};
+use crate::bitstream::{Error, InputBitStream, OutputBitStream, SeekSource, Sink, Source, Words};
+use crate::checkpoint::{self, Checkpoint, Restart};
+use crate::lossy::{self, Bound};
+use crate::{check_format, nan_bits, Decode, Encode, Float, Format, Seek, Value};
+use std::marker::PhantomData;
+
+// control bits 11 followed by 31 leading zeros and the max number of center bits, which can't be
+// an actual value since leading and center bits would add up to more than the float's width.
+// the reference format ends with a NaN instead and stores the center bits as is, with 64 as 0
+#[inline(always)]
+fn end_of_stream<T: Float>() -> (u64, u32) {
+    let len = 7 + T::CENTER_BITS;
+    ((1 << len) - 1, len)
+}
+
+/// Decoder state stored in a checkpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    pub curr: u64,
+    pub leading_zeros: u32,
+    pub trailing_zeros: u32,
+}
 
 #[derive(Debug)]
-pub struct Encoder {
+pub struct Encoder<T: Float = f64, S: Sink = Vec<u64>> {
     first: bool,
     curr: u64, // current float value as bits
     leading_zeros: u32,
     trailing_zeros: u32,
-    write: OutputBitStream,
+    write: OutputBitStream<S>,
+    count: u64,
+    checkpoint_every: u64,
+    checkpoints: Vec<Checkpoint<State>>,
+    lossy: Option<Bound>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
 // quick and dirty hack
-impl Encoder {
+impl<T: Float> Encoder<T> {
     pub fn new() -> Self {
+        Encoder::with_sink(Vec::new())
+    }
+}
+
+impl<T: Float> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
         Encoder {
             first: true,
             curr: 0,
             leading_zeros: u32::MAX,
             trailing_zeros: 0,
-            write: OutputBitStream::new(),
+            write: OutputBitStream::with_sink(sink),
+            count: 0,
+            checkpoint_every: 0,
+            checkpoints: Vec::new(),
+            lossy: None,
+            format: Format::Native,
+            float: PhantomData,
+        }
+    }
+
+    /// has to be set before the first value, panics for `Format::Reference` and f32
+    pub fn format(&mut self, format: Format) {
+        assert!(self.first, "format can't change in the middle of a stream");
+        check_format::<T>(format);
+        self.format = format;
+    }
+
+    /// records a checkpoint every `every` values from now on, 0 turns them off
+    pub fn checkpoint_every(&mut self, every: u64) {
+        self.checkpoint_every = every;
+    }
+
+    /// rounds every value encoded from now on as far as `bound` allows, see `lossy::round`
+    pub fn lossy(&mut self, bound: Bound) {
+        self.lossy = Some(bound);
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint<State>] {
+        &self.checkpoints
+    }
+
+    fn state(&self) -> State {
+        State {
+            curr: self.curr,
+            // u32::MAX means nothing was stored yet, the decoder starts at 0 instead
+            leading_zeros: if self.leading_zeros == u32::MAX {
+                0
+            } else {
+                self.leading_zeros
+            },
+            trailing_zeros: self.trailing_zeros,
         }
     }
 
-    pub fn insert_value(&mut self, value: f64) {
+    pub fn insert_value(&mut self, value: T) {
+        let value = value.to_bits64();
         if self.first {
             self.first = false;
-            self.write.write_bits(value.to_bits(), 64);
+            self.write.write_bits(value, T::BITS);
         } else {
-            let xor = self.curr ^ value.to_bits();
+            let xor = self.curr ^ value;
             if xor == 0 {
                 // identical
                 self.write.write_bit(0);
             } else {
                 self.write.write_bit(1);
-                let mut lead = xor.leading_zeros();
+                let mut lead = T::leading_zeros(xor);
                 let trail = xor.trailing_zeros();
 
                 if lead >= 32 {
@@ -42,15 +122,19 @@ impl Encoder {
 
                 if self.leading_zeros <= lead && self.trailing_zeros <= trail {
                     self.write.write_bit(0);
-                    let center_bits = 64 - self.leading_zeros - self.trailing_zeros;
+                    let center_bits = T::BITS - self.leading_zeros - self.trailing_zeros;
 
                     self.write
                         .write_bits(xor >> self.trailing_zeros, center_bits);
                 } else {
                     self.write.write_bit(1);
                     self.write.write_bits(lead as u64, 5);
-                    let center_bits = 64 - lead - trail;
-                    self.write.write_bits((center_bits as u64) - 1, 6);
+                    let center_bits = T::BITS - lead - trail;
+                    let center_field = match self.format {
+                        Format::Native => center_bits - 1,
+                        Format::Reference => center_bits & ((1 << T::CENTER_BITS) - 1),
+                    };
+                    self.write.write_bits(center_field as u64, T::CENTER_BITS);
                     self.write.write_bits(xor >> trail, center_bits);
 
                     self.leading_zeros = lead;
@@ -58,45 +142,68 @@ impl Encoder {
                 }
             }
         }
-        self.curr = value.to_bits();
+        self.curr = value;
     }
-    // TODO: timestamps?
 }
 
-impl Encode for Encoder {
-    fn encode_vec(values: &Vec<f64>) -> Self {
-        let mut enc = Encoder::new();
-        for &val in values {
-            enc.encode(val);
-        }
-        enc
-    }
+impl<T: Float, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
 
-    fn encode(&mut self, value: f64) {
+    fn encode(&mut self, value: T) {
+        let value = match self.lossy {
+            Some(bound) => lossy::round(value, bound),
+            None => value,
+        };
+        assert!(
+            self.format == Format::Native || value.to_bits64() != nan_bits::<T>(),
+            "NaN ends a reference stream"
+        );
         self.insert_value(value);
+
+        self.count += 1;
+        if checkpoint::due(self.checkpoint_every, self.count) {
+            self.checkpoints.push(Checkpoint {
+                index: self.count,
+                bit: self.write.bits_written(),
+                state: self.state(),
+            });
+        }
     }
 
-    fn close(self) -> (Box<[u64]>, u64) {
+    fn close(self) -> (S::Output, u64) {
         let mut this = self;
-        this.insert_value(f64::NAN);
-        let buffer = this.write.close();
-        let len = &buffer.len() * 64;
-        (buffer, len as u64)
+        match this.format {
+            // empty stream stays empty
+            Format::Native if this.first => {}
+            Format::Native => {
+                let (eos, len) = end_of_stream::<T>();
+                this.write.write_bits(eos, len);
+            }
+            Format::Reference => {
+                this.insert_value(T::from_bits64(nan_bits::<T>()));
+                this.write.write_bit(0);
+            }
+        }
+        this.write.close()
     }
 }
 
 #[derive(Debug)]
-pub struct Decoder {
+pub struct Decoder<T: Float = f64, S: Source = Words> {
     first: bool,
     done: bool,
     curr: u64, // current float value as bits
     leading_zeros: u32,
     trailing_zeros: u32,
-    read: InputBitStream,
+    read: InputBitStream<S>,
+    position: u64, // number of values decoded
+    checkpoints: Vec<Checkpoint<State>>,
+    format: Format,
+    float: PhantomData<T>,
 }
 
-impl Decoder {
-    pub fn new(read: InputBitStream) -> Self {
+impl<T: Float, S: Source> Decoder<T, S> {
+    pub fn new(read: InputBitStream<S>) -> Self {
         Decoder {
             first: true,
             done: false,
@@ -104,28 +211,71 @@ impl Decoder {
             leading_zeros: 0,
             trailing_zeros: 0,
             read,
+            position: 0,
+            checkpoints: Vec::new(),
+            format: Format::Native,
+            float: PhantomData,
         }
     }
 
+    /// e.g. `Format::Reference` for streams written by the reference implementation, those are
+    /// only padded to whole bytes, see `Bytes::padded`
+    pub fn format(&mut self, format: Format) {
+        check_format::<T>(format);
+        self.format = format;
+    }
+
+    /// checkpoints recorded by the encoder, used by `seek`
+    pub fn set_checkpoints(&mut self, checkpoints: Vec<Checkpoint<State>>) {
+        self.checkpoints = checkpoints;
+    }
+
     fn get_first(&mut self) -> Result<u64, Error> {
-        self.curr = self.read.read_bits(64)?;
+        // nothing was encoded
+        if self.read.is_empty()? {
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        self.curr = self.read.read_bits(T::BITS)?;
         Ok(self.curr)
     }
 
     fn get_value(&mut self) -> Result<u64, Error> {
-        let mut bit = self.read.read_bit()?;
-        if bit == Bit::One {
-            bit = self.read.read_bit()?;
-            if bit == Bit::One {
-                self.leading_zeros = self.read.read_bits(5)? as u32;
-                let center_bits = self.read.read_bits(6)? as u32 + 1;
-                self.trailing_zeros = 64 - self.leading_zeros - center_bits;
+        // both control bits, leading zeros and center bits in one go
+        let header_len = 2 + 5 + T::CENTER_BITS;
+        let header = self.read.peek_bits(header_len);
+        match header >> (header_len - 2) {
+            0 | 1 => {
+                self.read.consume(1)?;
+                return Ok(self.curr);
+            }
+            2 => self.read.consume(2)?,
+            _ => {
+                self.read.consume(header_len)?;
+                let leading_zeros = (header >> T::CENTER_BITS) as u32 & 0x1f;
+                let field = header & ((1 << T::CENTER_BITS) - 1);
+                let center_bits = match (self.format, field) {
+                    (Format::Native, field) => field as u32 + 1,
+                    (Format::Reference, 0) => T::BITS,
+                    (Format::Reference, field) => field as u32,
+                };
+                if leading_zeros + center_bits > T::BITS {
+                    if self.format == Format::Native
+                        && (leading_zeros, center_bits) == (31, 1 << T::CENTER_BITS)
+                    {
+                        self.done = true;
+                        return Err(Error::EOF);
+                    }
+                    return Err(Error::InvalidCode);
+                }
+                self.leading_zeros = leading_zeros;
+                self.trailing_zeros = T::BITS - self.leading_zeros - center_bits;
             }
-
-            let center_bits = 64 - self.leading_zeros - self.trailing_zeros;
-            let xor = self.read.read_bits(center_bits)?;
-            self.curr ^= xor << self.trailing_zeros;
         }
+
+        let center_bits = T::BITS - self.leading_zeros - self.trailing_zeros;
+        let xor = self.read.read_bits(center_bits)?;
+        self.curr ^= xor << self.trailing_zeros;
         Ok(self.curr)
     }
 
@@ -141,27 +291,98 @@ impl Decoder {
         } else {
             res = self.get_value()?;
         }
-
-        if res == NAN {
+        if self.format == Format::Reference && res == nan_bits::<T>() {
             self.done = true;
-            Err(Error::EOF)
-        } else {
-            Ok(res)
+            return Err(Error::EOF);
+        }
+
+        self.position += 1;
+        Ok(res)
+    }
+
+    // decode_into for any value type, xor hands out its integers with it. only the first value
+    // and the nan end marker of the reference format need get_next's checks, everything else
+    // goes straight from get_value into `out`
+    pub(crate) fn decode_as<U: Value>(&mut self, out: &mut [U]) -> Result<usize, Error> {
+        let mut n = 0;
+        while n < out.len() && (self.first || self.format == Format::Reference) {
+            match self.get_next() {
+                Ok(bits) => out[n] = U::from_bits64(bits),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            n += 1;
+        }
+        if self.done {
+            return Ok(n);
+        }
+
+        for val in &mut out[n..] {
+            match self.get_value() {
+                Ok(bits) => *val = U::from_bits64(bits),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            }
+            self.position += 1;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<T: Float, S: SeekSource> Seek<T> for Decoder<T, S> {
+    fn seek(&mut self, n: u64) -> Result<(), Error> {
+        match checkpoint::restart(&self.checkpoints, self.position, n) {
+            Restart::Continue => {}
+            Restart::Start => {
+                self.read.seek(0)?;
+                self.first = true;
+                self.done = false;
+                self.leading_zeros = 0;
+                self.trailing_zeros = 0;
+                self.position = 0;
+            }
+            Restart::At(cp) => {
+                self.read.seek(cp.bit)?;
+                self.first = false;
+                self.done = false;
+                self.curr = cp.state.curr;
+                self.leading_zeros = cp.state.leading_zeros;
+                self.trailing_zeros = cp.state.trailing_zeros;
+                self.position = cp.index;
+            }
         }
+
+        while self.position < n {
+            self.get_next()?;
+        }
+        Ok(())
     }
 }
 
-impl Decode for Decoder {
+impl<T: Float, S: Source> Decode<T> for Decoder<T, S> {
     fn get_next(&mut self) -> Result<u64, Error> {
         self.get_next()
     }
+
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        self.decode_as(out)
+    }
+}
+
+impl<T: Float, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Decoder, Encoder};
-    use crate::bitstream::InputBitStream;
-    use crate::Encode;
+    use crate::bitstream::{Bytes, Error, InputBitStream};
+    use crate::{reference, Encode, Format};
 
     #[test]
     fn simple_test() {
@@ -177,7 +398,7 @@ mod tests {
         }
 
         let (bytes, _) = encoder.close();
-        let mut decoder = Decoder::new(InputBitStream::new(bytes));
+        let mut decoder: Decoder = Decoder::new(InputBitStream::new(bytes));
         let mut datapoints = Vec::new();
 
         while let Ok(val) = decoder.get_next() {
@@ -186,4 +407,34 @@ mod tests {
 
         assert_eq!(datapoints, float_vec);
     }
+
+    #[test]
+    fn reference_test() {
+        let values: Vec<f64> = reference::values().into_iter().map(f64::from_bits).collect();
+        let mut encoder = Encoder::new();
+        encoder.format(Format::Reference);
+        for &val in &values {
+            encoder.encode(val);
+        }
+        reference::assert_stream(encoder.close(), "gorilla.bin");
+
+        let mut encoder = Encoder::<f64>::new();
+        encoder.format(Format::Reference);
+        reference::assert_stream(encoder.close(), "gorilla_empty.bin");
+
+        let bytes = reference::fixture("gorilla.bin");
+        let mut decoder: Decoder<f64, _> =
+            Decoder::new(InputBitStream::with_source(Bytes::padded(&bytes)));
+        decoder.format(Format::Reference);
+        for &val in &values {
+            assert_eq!(decoder.get_next(), Ok(val.to_bits()));
+        }
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+
+        let bytes = reference::fixture("gorilla_empty.bin");
+        let mut decoder: Decoder<f64, _> =
+            Decoder::new(InputBitStream::with_source(Bytes::padded(&bytes)));
+        decoder.format(Format::Reference);
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+    }
 }
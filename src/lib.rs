@@ -1,15 +1,22 @@
 #![warn(rust_2018_idioms, rust_2021_compatibility, nonstandard_style)]
 #![allow(unused_imports, dead_code)]
-#![feature(stdsimd)]
 
-use crate::bitstream::{Error, InputBitStream, OutputBitStream};
+use crate::bitstream::{Error, InputBitStream, OutputBitStream, Sink};
+use std::ops::Range;
 pub mod aligned;
 pub mod bitstream;
+pub mod block;
+pub mod checkpoint;
 pub mod chimp;
 pub mod chimpn;
+pub mod chunked;
+pub mod decimal;
+pub mod delta;
 pub mod gorilla;
-
-const NAN: u64 = 0b0111111111111000000000000000000000000000000000000000000000000000;
+pub mod lossy;
+pub mod simd;
+pub mod timestamp;
+pub mod xor;
 
 const LEADING_REPR_ENC: [u32; 64] = [
     0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 7, 7, 7, 7, 7, 7,
@@ -25,8 +32,191 @@ const LEADING_ROUND: [u32; 64] = [
     24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24,
 ];
 
+// 32 bit floats never have more than 31 leading zeros in a non-zero xor
+const LEADING_REPR_ENC_32: [u32; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 7, 7, 7, 7, 7, 7,
+];
+
+const LEADING_ROUND_32: [u32; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 8, 8, 8, 8, 12, 12, 12, 12, 16, 16, 18, 18, 20, 20, 22, 22, 24, 24, 24,
+    24, 24, 24, 24, 24,
+];
+
+/// Anything the codecs can store
+///
+/// Values are always handled as their bit pattern stored in the lower `BITS` bits of a `u64`,
+/// which is all `Encode`/`Decode` need to know about them.
+pub trait Value: Copy + PartialEq + std::fmt::Debug + Send + Sync + 'static {
+    /// width of the value in bits
+    const BITS: u32;
+
+    fn to_bits64(self) -> u64;
+    fn from_bits64(bits: u64) -> Self;
+}
+
+/// Floating point types that can be compressed
+///
+/// the float codecs only need the widths and tables below to support both `f32` and `f64`
+pub trait Float: Value {
+    /// bits needed to store the number of center bits
+    const CENTER_BITS: u32;
+    /// chimp only stores trailing zeros if there are more than THRESHOLD
+    const THRESHOLD: u32;
+    /// explicitly stored mantissa bits
+    const MANTISSA_BITS: u32;
+
+    const LEADING_REPR_ENC: &'static [u32];
+    const LEADING_ROUND: &'static [u32];
+
+    fn to_f64(self) -> f64;
+    /// nearest value of this type
+    fn from_f64(value: f64) -> Self;
+
+    /// leading zeros of a bit pattern relative to the width of the float
+    #[inline(always)]
+    fn leading_zeros(bits: u64) -> u32 {
+        bits.leading_zeros() - (64 - Self::BITS)
+    }
+}
+
+impl Value for f64 {
+    const BITS: u32 = 64;
+
+    #[inline(always)]
+    fn to_bits64(self) -> u64 {
+        self.to_bits()
+    }
+
+    #[inline(always)]
+    fn from_bits64(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+}
+
+impl Float for f64 {
+    const CENTER_BITS: u32 = 6;
+    const THRESHOLD: u32 = 6;
+    const MANTISSA_BITS: u32 = 52;
+
+    const LEADING_REPR_ENC: &'static [u32] = &LEADING_REPR_ENC;
+    const LEADING_ROUND: &'static [u32] = &LEADING_ROUND;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+impl Value for f32 {
+    const BITS: u32 = 32;
+
+    #[inline(always)]
+    fn to_bits64(self) -> u64 {
+        self.to_bits() as u64
+    }
+
+    #[inline(always)]
+    fn from_bits64(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+}
+
+impl Float for f32 {
+    const CENTER_BITS: u32 = 5;
+    const THRESHOLD: u32 = 5;
+    const MANTISSA_BITS: u32 = 23;
+
+    const LEADING_REPR_ENC: &'static [u32] = &LEADING_REPR_ENC_32;
+    const LEADING_ROUND: &'static [u32] = &LEADING_ROUND_32;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Value for i64 {
+    const BITS: u32 = 64;
+
+    #[inline(always)]
+    fn to_bits64(self) -> u64 {
+        self as u64
+    }
+
+    #[inline(always)]
+    fn from_bits64(bits: u64) -> Self {
+        bits as i64
+    }
+}
+
+impl Value for u64 {
+    const BITS: u32 = 64;
+
+    #[inline(always)]
+    fn to_bits64(self) -> u64 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_bits64(bits: u64) -> Self {
+        bits
+    }
+}
+
+/// Values that fill a whole `u64`, for the codecs that only work on 64 bit patterns
+///
+/// sealed, so a narrower type can't opt in and get truncated
+pub trait Value64: Value + sealed::Sealed {}
+
+impl Value64 for f64 {}
+impl Value64 for i64 {}
+impl Value64 for u64 {}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for f64 {}
+    impl Sealed for i64 {}
+    impl Sealed for u64 {}
+}
+
+/// Stream layout of the chimp, chimpn and gorilla codecs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// any value can be stored and chimpn streams start with their window size
+    #[default]
+    Native,
+    /// bit for bit what the paper authors' Java implementation writes, f64 only (see
+    /// `reference/`). streams end with a NaN, so the canonical NaN can't be stored, and chimpn's
+    /// window isn't in the stream
+    Reference,
+}
+
+// Double.NaN, which ends a reference stream
+#[inline(always)]
+pub(crate) fn nan_bits<T: Float>() -> u64 {
+    T::from_f64(f64::NAN).to_bits64()
+}
+
+pub(crate) fn check_format<T: Float>(format: Format) {
+    assert!(
+        format == Format::Native || T::BITS == 64,
+        "the reference format only exists for f64"
+    );
+}
+
 // not entirely necessary tbh
-#[derive(PartialEq, PartialOrd)]
+#[derive(Debug, PartialEq, PartialOrd)]
 pub enum Bit {
     Zero,
     One,
@@ -41,14 +231,548 @@ impl Bit {
     }
 }
 
-pub trait Encode {
-    fn encode_vec(values: &Vec<f64>) -> Self;
-    fn encode(&mut self, value: f64);
+pub trait Encode<T: Value = f64> {
+    /// where the compressed words end up, `Vec<u64>` for in-memory buffers
+    type Sink: Sink;
+
+    fn encode(&mut self, value: T);
+
+    /// same as calling `encode` for every value, overridden where a whole slice is faster
+    fn encode_slice(&mut self, values: &[T]) {
+        for &val in values {
+            self.encode(val);
+        }
+    }
 
-    /// returns Boxed Buffer and number of bits written
-    fn close(self) -> (Box<[u64]>, u64);
+    /// returns the sink's output (Boxed Buffer for `Vec<u64>`) and number of bits written
+    fn close(self) -> (<Self::Sink as Sink>::Output, u64);
 }
 
-pub trait Decode {
+pub trait Decode<T: Value = f64> {
     fn get_next(&mut self) -> Result<u64, Error>;
+
+    /// decodes up to `out.len()` values into `out` and returns how many, fewer only at the end
+    /// of the stream. on an error the values before the invalid one are in `out` as well
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        for (n, val) in out.iter_mut().enumerate() {
+            *val = match self.get_next() {
+                Ok(bits) => T::from_bits64(bits),
+                Err(Error::EOF) => return Ok(n),
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(out.len())
+    }
+
+    /// decodes all remaining values, only stopping without an error at the end of the stream
+    fn decode_to_vec(&mut self) -> Result<Vec<T>, Error> {
+        let mut values = Vec::new();
+        let mut len = 0;
+        loop {
+            // grows like push would, without going through it for every value
+            let want = len.clamp(DECODE_CHUNK, 1 << 20);
+            values.resize(len + want, T::from_bits64(0));
+            let n = self.decode_into(&mut values[len..])?;
+            len += n;
+            if n < want {
+                break;
+            }
+        }
+        values.truncate(len);
+        Ok(values)
+    }
+}
+
+// smallest batch decode_to_vec asks for
+const DECODE_CHUNK: usize = 1024;
+
+/// Decoders that can jump to any value, starting from the nearest checkpoint
+pub trait Seek<T: Value = f64>: Decode<T> {
+    /// the next call to `get_next` returns value `n`, EOF if the stream is shorter than that
+    fn seek(&mut self, n: u64) -> Result<(), Error>;
+
+    /// values `range.start` up to `range.end`, EOF if the stream ends before that
+    fn decode_range(&mut self, range: Range<u64>) -> Result<Vec<T>, Error> {
+        self.seek(range.start)?;
+        range
+            .map(|_| self.get_next().map(T::from_bits64))
+            .collect()
+    }
+}
+
+// turns the result of get_next into an iterator item, EOF ends the iteration
+#[inline(always)]
+fn next_item<T, U>(res: Result<T, Error>, f: impl FnOnce(T) -> U) -> Option<Result<U, Error>> {
+    match res {
+        Ok(val) => Some(Ok(f(val))),
+        Err(Error::EOF) => None,
+        Err(err) => Some(Err(err)),
+    }
+}
+
+// golden streams written by the reference implementation, see reference/Generate.java
+#[cfg(test)]
+pub(crate) mod reference {
+    pub(crate) fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!("reference/fixtures/{name}")).unwrap()
+    }
+
+    pub(crate) fn values() -> Vec<u64> {
+        fixture("values.bin")
+            .chunks(8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    // the reference pads to whole bytes, the bitstream to whole words
+    pub(crate) fn assert_stream((words, _): (Box<[u64]>, u64), name: &str) {
+        let expected = fixture(name);
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        assert_eq!(bytes.len(), expected.len().div_ceil(8) * 8, "{name}");
+        assert!(bytes[..expected.len()] == expected[..], "{name} differs");
+        assert!(bytes[expected.len()..].iter().all(|&b| b == 0), "{name}");
+    }
+}
+
+// every number in the csv/txt files under datasets/, in the order they appear
+#[cfg(test)]
+pub(crate) mod datasets {
+    use std::path::Path;
+
+    pub(crate) fn values() -> Vec<f64> {
+        let mut values = Vec::new();
+        read_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("datasets"), &mut values);
+        values
+    }
+
+    fn read_dir(dir: &Path, values: &mut Vec<f64>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                read_dir(&path, values);
+            } else if path.extension().is_some_and(|ext| ext == "csv" || ext == "txt") {
+                let text = std::fs::read_to_string(&path).unwrap();
+                values.extend(
+                    text.split(|c: char| c == ',' || c.is_whitespace())
+                        .filter_map(|field| field.parse::<f64>().ok()),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitstream::{Error, InputBitStream, ReadSource, WriteSink};
+    use crate::block::{Block, Codec};
+    use crate::checkpoint::Checkpoint;
+    use crate::chunked::Chunked;
+    use crate::simd::Simd;
+    use crate::{aligned, chimp, chimpn, decimal, delta, gorilla, timestamp, xor};
+    use crate::{Decode, Encode, Float, Seek, Value};
+    use proptest::prelude::*;
+    use std::io::Cursor;
+
+    // arbitrary bit patterns (NaN payloads, subnormals, ...), the values codecs tend to trip over,
+    // and a small pool so there are repeats and matching lsbs
+    fn float_bits() -> impl Strategy<Value = u64> {
+        let special = [
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            -f64::NAN,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            f64::EPSILON,
+        ];
+        prop_oneof![
+            any::<u64>(),
+            (0..1u64 << 52, any::<bool>()).prop_map(|(m, neg)| m | (neg as u64) << 63),
+            prop::sample::select(special.map(f64::to_bits).to_vec()),
+            (0..8u64).prop_map(|i| (100.0 + i as f64 * 0.25).to_bits()),
+            (-100_000..100_000i64, 0..6i32).prop_map(|(i, e)| (i as f64 / 10f64.powi(e)).to_bits()),
+        ]
+    }
+
+    fn float_bits32() -> impl Strategy<Value = u64> {
+        let special = [0.0, -0.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN, f32::MIN_POSITIVE];
+        prop_oneof![
+            any::<u32>().prop_map(u64::from),
+            (0..1u64 << 23, any::<bool>()).prop_map(|(m, neg)| m | (neg as u64) << 31),
+            prop::sample::select(special.map(|v| v.to_bits() as u64).to_vec()),
+            (0..8u32).prop_map(|i| (100.0 + i as f32 * 0.25).to_bits() as u64),
+            (-10_000..10_000i32, 0..4i32)
+                .prop_map(|(i, e)| (i as f32 / 10f32.powi(e)).to_bits() as u64),
+        ]
+    }
+
+    // counters with small steps, jumps, and anything at all
+    fn int_bits() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            any::<u64>(),
+            (0..1000u64).prop_map(|i| 1_000_000 + i * 7),
+            prop::sample::select(vec![0, 1, u64::MAX, 1 << 63, i64::MAX as u64]),
+        ]
+    }
+
+    fn round_trip<T: Value, E: Encode<T, Sink = Vec<u64>>, D: Decode<T>>(
+        mut encoder: E,
+        new_decoder: impl Fn(InputBitStream) -> D,
+        bits: &[u64],
+    ) -> Result<(), TestCaseError> {
+        let values: Vec<T> = bits.iter().map(|&val| T::from_bits64(val)).collect();
+        encoder.encode_slice(&values);
+        let (words, _) = encoder.close();
+        let mut decoder = new_decoder(InputBitStream::new(words.clone()));
+        for &val in bits {
+            prop_assert_eq!(decoder.get_next(), Ok(val));
+        }
+        prop_assert_eq!(decoder.get_next(), Err(Error::EOF));
+
+        // batches that don't line up with anything
+        let mut decoder = new_decoder(InputBitStream::new(words));
+        let mut out = [T::from_bits64(0); 37];
+        let mut decoded = Vec::new();
+        loop {
+            let n = decoder.decode_into(&mut out).unwrap();
+            decoded.extend(out[..n].iter().map(|val| val.to_bits64()));
+            if n < out.len() {
+                break;
+            }
+        }
+        prop_assert_eq!(decoded, bits);
+        prop_assert_eq!(decoder.decode_into(&mut out), Ok(0));
+        Ok(())
+    }
+
+    // NaNs with payloads and infinities have to come back bit for bit
+    fn nan_check<E: Encode<f64, Sink = Vec<u64>>, D: Decode<f64>>(
+        encoder: E,
+        new_decoder: impl Fn(InputBitStream) -> D,
+    ) {
+        let values = [
+            1.0,
+            f64::NAN,
+            48.8,
+            f64::NAN,
+            f64::NAN,
+            -f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_beef),
+            f64::from_bits(0xfff8_dead_0000_0000),
+            f64::INFINITY,
+            48.9,
+        ];
+        round_trip(encoder, new_decoder, &values.map(f64::to_bits)).unwrap();
+    }
+
+    fn empty_check<E: Encode<f64, Sink = Vec<u64>>, D: Decode<f64>>(
+        encoder: E,
+        new_decoder: impl Fn(InputBitStream) -> D,
+    ) {
+        round_trip(encoder, new_decoder, &[]).unwrap();
+    }
+
+    fn f32_check<E: Encode<f32, Sink = Vec<u64>>, D: Decode<f32>>(
+        encoder: E,
+        new_decoder: impl Fn(InputBitStream) -> D,
+    ) {
+        let values: [f32; 22] = [
+            49.4, 48.8, 46.4, 47.9, 48.7, 48.9, 48.8, 46.4, 47.9, 48.7, 48.9, 1.0, 1.0, 16.42,
+            0.00123, 24435., 0., -0., f32::NAN, f32::INFINITY, 420.69, 64.2,
+        ];
+        round_trip(encoder, new_decoder, &values.map(|val| val.to_bits() as u64)).unwrap();
+    }
+
+    // collecting the iterator gives the same as decode_to_vec, and both end the stream
+    fn iterator_check<E, D>(mut encoder: E, new_decoder: impl Fn(InputBitStream) -> D)
+    where
+        E: Encode<f64, Sink = Vec<u64>>,
+        D: Decode<f64> + Iterator<Item = Result<f64, Error>>,
+    {
+        let values = [49.4, 48.8, 46.4, 47.9, 48.7, 48.9, 48.8, 46.4, 47.9, 48.7, 48.9];
+        encoder.encode_slice(&values);
+        let (words, _) = encoder.close();
+        let decoder = new_decoder(InputBitStream::new(words.clone()));
+        assert_eq!(decoder.collect::<Result<Vec<_>, _>>(), Ok(values.to_vec()));
+
+        let mut decoder = new_decoder(InputBitStream::new(words));
+        assert_eq!(decoder.decode_to_vec(), Ok(values.to_vec()));
+        assert_eq!(decoder.next(), None);
+    }
+
+    // written to an io::Write and read back from an io::Read
+    fn stream_check<E, D>(
+        mut encoder: E,
+        new_decoder: impl Fn(InputBitStream<ReadSource<Cursor<Vec<u8>>>>) -> D,
+    ) where
+        E: Encode<f64, Sink = WriteSink<Vec<u8>>>,
+        D: Decode<f64>,
+    {
+        let values: Vec<f64> = (0..1000).map(|i| (i as f64 / 10.0).cos() * 42.0).collect();
+        encoder.encode_slice(&values);
+        let (bytes, len) = encoder.close();
+        let bytes = bytes.unwrap();
+        assert_eq!(bytes.len() as u64 * 8, len);
+
+        let mut decoder = new_decoder(InputBitStream::from_reader(Cursor::new(bytes)));
+        assert_eq!(decoder.decode_to_vec(), Ok(values));
+    }
+
+    // decode_range with the checkpoints the encoder recorded every 64 values, and without any
+    fn seek_check<E, D, S: Clone>(
+        mut encoder: E,
+        checkpoint_every: fn(&mut E, u64),
+        checkpoints: fn(&E) -> &[Checkpoint<S>],
+        new_decoder: impl Fn(InputBitStream) -> D,
+        set_checkpoints: fn(&mut D, Vec<Checkpoint<S>>),
+    ) where
+        E: Encode<f64, Sink = Vec<u64>>,
+        D: Seek<f64>,
+    {
+        let values: Vec<f64> = (0..1000)
+            .map(|i| (i as f64 * 0.1).sin() * 10.0 + 100.0)
+            .collect();
+        let range = |a: u64, b: u64| Ok(values[a as usize..b as usize].to_vec());
+
+        checkpoint_every(&mut encoder, 64);
+        encoder.encode_slice(&values);
+        let recorded = checkpoints(&encoder).to_vec();
+        assert_eq!(recorded.len(), 1000 / 64);
+
+        let (words, _) = encoder.close();
+        let mut decoder = new_decoder(InputBitStream::new(words.clone()));
+        set_checkpoints(&mut decoder, recorded);
+        for (a, b) in [(500, 510), (0, 3), (999, 1000), (64, 200), (130, 130), (700, 1000)] {
+            assert_eq!(decoder.decode_range(a..b), range(a, b));
+        }
+        assert_eq!(decoder.seek(1001), Err(Error::EOF));
+
+        // without checkpoints everything is decoded from the start
+        let mut decoder = new_decoder(InputBitStream::new(words));
+        assert_eq!(decoder.decode_range(900..910), range(900, 910));
+        assert_eq!(decoder.decode_range(10..20), range(10, 20));
+    }
+
+    // has to end without a panic, the result doesn't matter
+    fn drain<T: Value>(decoder: &mut dyn Decode<T>) {
+        while decoder.get_next().is_ok() {}
+    }
+
+    // decode_into has to stop at the same value as get_next, with the same error
+    fn same_as_get_next<T: Value, D: Decode<T>>(new_decoder: impl Fn() -> D) {
+        let mut decoder = new_decoder();
+        let mut bits = Vec::new();
+        let end = loop {
+            match decoder.get_next() {
+                Ok(val) => bits.push(val),
+                Err(err) => break err,
+            }
+        };
+
+        let mut out = vec![T::from_bits64(0); bits.len() + 1];
+        let res = new_decoder().decode_into(&mut out);
+        match end {
+            Error::EOF => assert_eq!(res, Ok(bits.len())),
+            err => assert_eq!(res, Err(err)),
+        }
+        let decoded: Vec<u64> = out[..bits.len()].iter().map(|val| val.to_bits64()).collect();
+        assert_eq!(decoded, bits);
+    }
+
+    fn decode_garbage<T: Float>(bytes: &[u8]) {
+        let r = || InputBitStream::from_bytes(bytes);
+        same_as_get_next::<T, _>(|| chimp::Decoder::new(r()));
+        same_as_get_next::<T, _>(|| chimpn::Decoder::new(r()));
+        same_as_get_next::<T, _>(|| gorilla::Decoder::new(r()));
+        same_as_get_next::<T, _>(|| aligned::Decoder::new(r()));
+        same_as_get_next::<T, _>(|| decimal::Decoder::new(r()));
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_f64(
+            bits in prop::collection::vec(float_bits(), 0..400),
+            log_n in 1..=12u32,
+        ) {
+            let window = 1 << log_n;
+            round_trip::<f64, _, _>(chimp::Encoder::new(), chimp::Decoder::new, &bits)?;
+            round_trip::<f64, _, _>(
+                chimpn::Encoder::with_window(window),
+                chimpn::Decoder::new,
+                &bits,
+            )?;
+            round_trip::<f64, _, _>(gorilla::Encoder::new(), gorilla::Decoder::new, &bits)?;
+            round_trip::<f64, _, _>(decimal::Encoder::new(), decimal::Decoder::new, &bits)?;
+            // aligned metadata only has room for windows up to 128
+            round_trip::<f64, _, _>(
+                aligned::Encoder::with_window(window.min(128)),
+                aligned::Decoder::new,
+                &bits,
+            )?;
+        }
+
+        #[test]
+        fn round_trip_f32(
+            bits in prop::collection::vec(float_bits32(), 0..400),
+            log_n in 1..=7u32,
+        ) {
+            let window = 1 << log_n;
+            round_trip::<f32, _, _>(chimp::Encoder::new(), chimp::Decoder::new, &bits)?;
+            round_trip::<f32, _, _>(
+                chimpn::Encoder::with_window(window),
+                chimpn::Decoder::new,
+                &bits,
+            )?;
+            round_trip::<f32, _, _>(gorilla::Encoder::new(), gorilla::Decoder::new, &bits)?;
+            round_trip::<f32, _, _>(decimal::Encoder::new(), decimal::Decoder::new, &bits)?;
+            round_trip::<f32, _, _>(
+                aligned::Encoder::with_window(window),
+                aligned::Decoder::new,
+                &bits,
+            )?;
+        }
+
+        #[test]
+        fn round_trip_simd(bits in prop::collection::vec(float_bits(), 0..400)) {
+            let values: Vec<f64> = bits.iter().map(|&v| f64::from_bits(v)).collect();
+            let mut encoder = chimp::Encoder::<f64>::new();
+            encoder.encode_slice(&values);
+            let (expected, _) = encoder.close();
+            for simd in Simd::ALL.into_iter().filter(|simd| simd.is_available()) {
+                let mut encoder = chimp::Encoder::new();
+                encoder.encode_slice_with(&values, simd);
+                let (words, _) = encoder.close();
+                prop_assert_eq!(&words, &expected);
+
+                let mut decoder: chimp::Decoder = chimp::Decoder::new(InputBitStream::new(words));
+                for &val in &bits {
+                    prop_assert_eq!(decoder.get_next(), Ok(val));
+                }
+                prop_assert_eq!(decoder.get_next(), Err(Error::EOF));
+            }
+        }
+
+        // same as the fuzz targets, just a lot less thorough
+        #[test]
+        fn decode_garbage_test(bytes in prop::collection::vec(any::<u8>(), 0..300)) {
+            decode_garbage::<f64>(&bytes);
+            decode_garbage::<f32>(&bytes);
+            let block = Block::read_from(&bytes[..]).and_then(Block::into_decoder::<f64>);
+            if let Ok(mut decoder) = block {
+                drain(&mut decoder);
+            }
+            let _ = Chunked::read_from(&bytes[..]).map(|chunked| chunked.decode::<f64>());
+
+            let r = || InputBitStream::from_bytes(&bytes);
+            same_as_get_next::<i64, _>(|| delta::Decoder::new(r()));
+            same_as_get_next::<u64, _>(|| xor::Decoder::new(r()));
+            same_as_get_next::<i64, _>(|| timestamp::Decoder::new(r()));
+        }
+
+        #[test]
+        fn round_trip_int(bits in prop::collection::vec(int_bits(), 0..400)) {
+            round_trip::<i64, _, _>(delta::Encoder::new(), delta::Decoder::new, &bits)?;
+            round_trip::<u64, _, _>(delta::Encoder::new(), delta::Decoder::new, &bits)?;
+            round_trip::<i64, _, _>(xor::Encoder::new(), xor::Decoder::new, &bits)?;
+            round_trip::<u64, _, _>(xor::Encoder::new(), xor::Decoder::new, &bits)?;
+        }
+
+        // valid words with a single flipped bit have to fail or decode to something, not panic
+        #[test]
+        fn flipped_bit_test(
+            bits in prop::collection::vec(float_bits(), 1..100),
+            flip in any::<prop::sample::Index>(),
+        ) {
+            let values: Vec<f64> = bits.iter().map(|&v| f64::from_bits(v)).collect();
+            let codecs = [
+                Codec::Chimp,
+                Codec::ChimpN,
+                Codec::Gorilla,
+                Codec::Aligned,
+                Codec::Decimal,
+            ];
+            for codec in codecs {
+                let (mut words, len) = codec.encode(&values);
+                let bit = flip.index(len as usize);
+                words[bit / 64] ^= 1 << (63 - bit % 64);
+                drain(codec.decoder::<f64, _>(InputBitStream::new(words)).as_mut());
+            }
+        }
+    }
+
+    #[test]
+    fn nan_test() {
+        nan_check(chimp::Encoder::new(), chimp::Decoder::new);
+        nan_check(chimpn::Encoder::new(), chimpn::Decoder::new);
+        nan_check(gorilla::Encoder::new(), gorilla::Decoder::new);
+        nan_check(aligned::Encoder::new(), aligned::Decoder::new);
+    }
+
+    #[test]
+    fn empty_test() {
+        empty_check(chimp::Encoder::new(), chimp::Decoder::new);
+        empty_check(chimpn::Encoder::new(), chimpn::Decoder::new);
+        empty_check(gorilla::Encoder::new(), gorilla::Decoder::new);
+        empty_check(aligned::Encoder::new(), aligned::Decoder::new);
+    }
+
+    #[test]
+    fn f32_test() {
+        f32_check(chimp::Encoder::new(), chimp::Decoder::new);
+        f32_check(chimpn::Encoder::new(), chimpn::Decoder::new);
+        f32_check(gorilla::Encoder::new(), gorilla::Decoder::new);
+        f32_check(aligned::Encoder::new(), aligned::Decoder::new);
+    }
+
+    #[test]
+    fn iterator_test() {
+        iterator_check(chimp::Encoder::new(), chimp::Decoder::new);
+        iterator_check(chimpn::Encoder::new(), chimpn::Decoder::new);
+        iterator_check(gorilla::Encoder::new(), gorilla::Decoder::new);
+        iterator_check(aligned::Encoder::new(), aligned::Decoder::new);
+    }
+
+    #[test]
+    fn stream_test() {
+        let sink = || WriteSink::new(Vec::new());
+        stream_check(chimp::Encoder::with_sink(sink()), chimp::Decoder::new);
+        stream_check(chimpn::Encoder::with_sink(sink()), chimpn::Decoder::new);
+        stream_check(gorilla::Encoder::with_sink(sink()), gorilla::Decoder::new);
+        stream_check(aligned::Encoder::with_sink(sink()), aligned::Decoder::new);
+    }
+
+    #[test]
+    fn seek_test() {
+        seek_check(
+            chimp::Encoder::new(),
+            chimp::Encoder::checkpoint_every,
+            chimp::Encoder::checkpoints,
+            chimp::Decoder::new,
+            chimp::Decoder::set_checkpoints,
+        );
+        seek_check(
+            chimpn::Encoder::new(),
+            chimpn::Encoder::checkpoint_every,
+            chimpn::Encoder::checkpoints,
+            chimpn::Decoder::new,
+            chimpn::Decoder::set_checkpoints,
+        );
+        seek_check(
+            gorilla::Encoder::new(),
+            gorilla::Encoder::checkpoint_every,
+            gorilla::Encoder::checkpoints,
+            gorilla::Decoder::new,
+            gorilla::Decoder::set_checkpoints,
+        );
+        seek_check(
+            aligned::Encoder::new(),
+            aligned::Encoder::checkpoint_every,
+            aligned::Encoder::checkpoints,
+            aligned::Decoder::new,
+            aligned::Decoder::set_checkpoints,
+        );
+    }
 }
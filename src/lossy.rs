@@ -0,0 +1,202 @@
+use crate::Float;
+
+// Lossy mode: low mantissa bits that are below the allowed error get rounded away before a value
+// is encoded, so xors end up with a lot more trailing zeros (the flag 01 paths in chimp/chimpn,
+// shorter meaningful bits in gorilla, fewer data bytes in aligned)
+//
+// decimal-rounded sensor values like 48.8 are the main use case, their mantissa is an endless
+// 0110 pattern that never has any trailing zeros. chimpn and aligned already find references
+// with the same pattern through their lsb lookup, so they gain a lot less than chimp and gorilla
+
+/// How far an encoded value may be off from the original
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    /// |decoded - value| <= bound
+    Absolute(f64),
+    /// |decoded - value| <= bound * |value|
+    Relative(f64),
+    /// decoded values still round to the same number of decimal digits after the point, so
+    /// values that had at most that many digits come back out when printed with that precision
+    Digits(u32),
+}
+
+impl Bound {
+    #[inline(always)]
+    fn allows(self, value: f64, rounded: f64) -> bool {
+        match self {
+            Bound::Absolute(bound) => (rounded - value).abs() <= bound,
+            Bound::Relative(bound) => (rounded - value).abs() <= bound * value.abs(),
+            Bound::Digits(digits) => {
+                // strictly inside, formatting rounds ties to even
+                let scale = 10f64.powi(digits as i32);
+                (rounded * scale - (value * scale).round()).abs() < 0.5
+            }
+        }
+    }
+}
+
+/// `value` rounded to as few mantissa bits as the bound allows
+///
+/// NaN, infinities and zeros are returned as they are
+pub fn round<T: Float>(value: T, bound: Bound) -> T {
+    let orig = value.to_f64();
+    if !orig.is_finite() || orig == 0.0 {
+        return value;
+    }
+
+    let bits = value.to_bits64();
+    let sign = bits & (1 << (T::BITS - 1));
+    let magnitude = bits ^ sign;
+
+    // rounds to the nearest multiple of 2^k, carrying into the exponent is fine since the
+    // magnitude bits are ordered like the values they represent
+    let rounded = |k: u32| {
+        if k == 0 {
+            return bits;
+        }
+        let half = 1 << (k - 1);
+        sign | ((magnitude + half) & !((1 << k) - 1))
+    };
+    let allowed = |k: u32| {
+        let rounded = T::from_bits64(rounded(k)).to_f64();
+        rounded.is_finite() && bound.allows(orig, rounded)
+    };
+
+    // rounding off more bits never gets closer, so the largest k can be searched for
+    let (mut lo, mut hi) = (0, T::MANTISSA_BITS);
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if allowed(mid) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    T::from_bits64(rounded(lo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{round, Bound};
+    use crate::bitstream::InputBitStream;
+    use crate::{aligned, chimp, chimpn, gorilla, Decode, Encode, Float};
+
+    // sensor-like values with two decimal digits, slowly drifting so they rarely repeat
+    fn readings() -> Vec<f64> {
+        (0..5000)
+            .map(|i| {
+                let i = i as f64;
+                ((i * 0.37 + i.sin() * 5.0 + 1000.0) * 100.0).round() / 100.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bound_test() {
+        let values: Vec<f64> = (1..2000)
+            .map(|i| (i as f64 * 0.37).sin() * 10f64.powi(i % 20 - 10))
+            .collect();
+
+        for &val in &values {
+            for bound in [1e-3, 1e-9, 0.0] {
+                let rounded = round(val, Bound::Absolute(bound));
+                assert!((rounded - val).abs() <= bound);
+
+                let rounded = round(val, Bound::Relative(bound));
+                assert!((rounded - val).abs() <= bound * val.abs());
+            }
+            let rounded = round(val as f32, Bound::Relative(1e-3));
+            assert!((rounded - val as f32).abs() <= 1e-3 * (val as f32).abs());
+        }
+
+        // nothing to round off
+        assert_eq!(round(0.1, Bound::Absolute(0.0)), 0.1);
+        assert_eq!(round(1.0, Bound::Absolute(1e-3)), 1.0);
+        // everything can go, as long as the value stays the same power of two
+        assert_eq!(round(3.0, Bound::Absolute(1.0)), 4.0);
+        assert_eq!(round(-3.0, Bound::Relative(0.5)), -4.0);
+        // rounding up to infinity isn't allowed
+        assert_eq!(round(f64::MAX, Bound::Relative(1.0)), f64::MAX);
+    }
+
+    #[test]
+    fn special_test() {
+        for val in [f64::NAN, -f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 0.0, -0.0] {
+            assert_eq!(round(val, Bound::Absolute(1.0)).to_bits(), val.to_bits());
+        }
+        let nan = f64::from_bits(0x7ff0_0000_0000_beef);
+        assert_eq!(round(nan, Bound::Digits(0)).to_bits(), nan.to_bits());
+
+        // subnormals are rounded like any other value
+        let tiny = f64::from_bits(0x000f_ffff_ffff_ffff);
+        assert_eq!(round(tiny, Bound::Absolute(1e-300)), f64::MIN_POSITIVE);
+    }
+
+    #[test]
+    fn digits_test() {
+        for val in readings() {
+            let rounded = round(val, Bound::Digits(2));
+            assert_eq!(format!("{:.2}", rounded), format!("{:.2}", val));
+            assert!(rounded.to_bits().trailing_zeros() > 30);
+        }
+
+        for val in [48.8f32, 0.1, 1234.5, -7.25] {
+            let rounded = round(val, Bound::Digits(2));
+            assert_eq!(format!("{:.2}", rounded), format!("{:.2}", val));
+        }
+    }
+
+    fn round_trip<E: Encode<Sink = Vec<u64>>, D: Decode>(
+        mut encoder: E,
+        decoder: impl FnOnce(InputBitStream) -> D,
+        values: &[f64],
+    ) -> (Vec<f64>, u64) {
+        for &val in values {
+            encoder.encode(val);
+        }
+        let (words, len) = encoder.close();
+        (decoder(InputBitStream::new(words)).decode_to_vec().unwrap(), len)
+    }
+
+    #[test]
+    fn codec_test() {
+        let values = readings();
+        let bound = Bound::Digits(2);
+        let expected: Vec<f64> = values.iter().map(|&v| round(v, bound)).collect();
+
+        let mut sizes = Vec::new();
+
+        let (_, lossless) = round_trip(chimp::Encoder::new(), chimp::Decoder::new, &values);
+        let mut encoder = chimp::Encoder::new();
+        encoder.lossy(bound);
+        let (decoded, lossy) = round_trip(encoder, chimp::Decoder::new, &values);
+        assert_eq!(decoded, expected);
+        sizes.push((lossless, lossy));
+
+        let (_, lossless) = round_trip(chimpn::Encoder::new(), chimpn::Decoder::new, &values);
+        let mut encoder = chimpn::Encoder::new();
+        encoder.lossy(bound);
+        let (decoded, lossy) = round_trip(encoder, chimpn::Decoder::new, &values);
+        assert_eq!(decoded, expected);
+        sizes.push((lossless, lossy));
+
+        let (_, lossless) = round_trip(gorilla::Encoder::new(), gorilla::Decoder::new, &values);
+        let mut encoder = gorilla::Encoder::new();
+        encoder.lossy(bound);
+        let (decoded, lossy) = round_trip(encoder, gorilla::Decoder::new, &values);
+        assert_eq!(decoded, expected);
+        sizes.push((lossless, lossy));
+
+        let (_, lossless) = round_trip(aligned::Encoder::new(), aligned::Decoder::new, &values);
+        let mut encoder = aligned::Encoder::new();
+        encoder.lossy(bound);
+        let (decoded, lossy) = round_trip(encoder, aligned::Decoder::new, &values);
+        assert_eq!(decoded, expected);
+        sizes.push((lossless, lossy));
+
+        // chimp and gorilla, the others don't get much out of it (see the top)
+        for (lossless, lossy) in [sizes[0], sizes[2]] {
+            assert!(lossy < lossless / 2, "{lossy} vs {lossless} bits");
+        }
+    }
+}
@@ -1,14 +1,17 @@
-use chimp_lib::{aligned, bitstream::InputBitStream, chimp, chimpn, gorilla, Decode, Encode};
+use chimp_lib::{aligned, bitstream::InputBitStream, chimp, chimpn, decimal, gorilla};
+use chimp_lib::{block::Codec, chunked::Chunked};
+use chimp_lib::{Decode, Encode};
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum ChimpType {
     Chimp,
-    ChimpN,
+    ChimpN(usize),
     SIMD,
     Gorilla,
     Rayon,
     Patas,
+    Decimal,
 }
 
 // simple benchmark/test/comparison with different datasets
@@ -30,12 +33,12 @@ fn main() {
     println!("-----------------CHIMP------------------------------");
     test_compression(&paths, ChimpType::Chimp);
 
-    for size in vec![128u64] {
+    for size in [16, 32, 64, 128, 256] {
         println!(
             "-----------------CHIMP{:03}---------------------------",
             size
         );
-        test_compression(&paths, ChimpType::ChimpN);
+        test_compression(&paths, ChimpType::ChimpN(size));
     }
 
     println!("-----------------GORILLA----------------------------");
@@ -49,6 +52,25 @@ fn main() {
 
     println!("-----------------PATAS------------------------------");
     test_compression(&paths, ChimpType::Patas);
+
+    println!("-----------------DECIMAL----------------------------");
+    test_compression(&paths, ChimpType::Decimal);
+
+    println!("-----------------PATAS VS CHIMP128 [DECODE]---------");
+    compare_decode(&paths);
+}
+
+pub fn read_values(path: &str, float_idx: usize) -> Vec<f64> {
+    let reader = csv::Reader::from_path(path);
+    let mut values: Vec<f64> = Vec::new();
+
+    for record in reader.unwrap().records() {
+        let string_record = record.unwrap();
+        let val = string_record[float_idx].to_string();
+        let val = val.parse::<f64>().unwrap();
+        values.push(val);
+    }
+    values
 }
 
 pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
@@ -61,28 +83,20 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
     for _ in 0..n {
         for (path, float_idx) in paths {
             // println!("[[DATASET: {}]]", path);
-            let reader = csv::Reader::from_path(path);
-            let mut values: Vec<f64> = Vec::new();
-
-            for record in reader.unwrap().records() {
-                let string_record = record.unwrap();
-                let val = string_record[*float_idx].to_string();
-                let val = val.parse::<f64>().unwrap();
-                values.push(val);
-            }
+            let values = read_values(path, *float_idx);
             let enc_speed: Duration;
             let dec_speed: Duration;
             let compr_ratio: f64;
             match enc_t {
                 ChimpType::Rayon => {
                     let now = Instant::now();
-                    let encoded = chimp::Encoder::threaded(&values);
+                    let encoded = Chunked::encode(Codec::Chimp, &values);
                     let new_now = Instant::now();
                     // println!(
                     //     "[encode] per 1000 values: {:?}",
                     //     (new_now - now) / (values.len() / 1000) as u32
                     // );
-                    let size: u64 = encoded.iter().fold(0, |acc, tup| acc + tup.1);
+                    let size: u64 = encoded.bits();
                     // println!(
                     //     "average bits per val: {}",
                     //     size as f64 / values.len() as f64
@@ -91,7 +105,7 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
                     compr_ratio = size as f64 / values.len() as f64;
 
                     let now = Instant::now();
-                    let decoded = chimp::Decoder::decode_threaded(encoded);
+                    let decoded: Vec<f64> = encoded.decode().unwrap();
                     let new_now = Instant::now();
 
                     dec_speed = (new_now - now) / (decoded.len() / 1000) as u32;
@@ -104,9 +118,7 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
                 ChimpType::SIMD => {
                     let mut chimp_simd = chimp::Encoder::new();
                     let now = Instant::now();
-                    unsafe {
-                        chimp_simd.simd_vec(&values);
-                    }
+                    chimp_simd.encode_slice(&values);
                     let new_now = Instant::now();
                     let (buffer, size) = chimp_simd.close();
                     // println!(
@@ -120,12 +132,9 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
                     enc_speed = (new_now - now) / (values.len() / 1000) as u32;
                     compr_ratio = size as f64 / values.len() as f64;
 
-                    let mut dec = chimp::Decoder::new(InputBitStream::new(buffer));
-                    let mut vec: Vec<f64> = Vec::new();
+                    let mut dec: chimp::Decoder = chimp::Decoder::new(InputBitStream::new(buffer));
                     let now = Instant::now();
-                    while let Ok(dec_val) = dec.get_next() {
-                        vec.push(f64::from_bits(dec_val));
-                    }
+                    let vec = dec.decode_to_vec().unwrap();
                     let new_now = Instant::now();
 
                     dec_speed = (new_now - now) / (vec.len() / 1000) as u32;
@@ -142,11 +151,11 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
                         ChimpType::Chimp,
                     );
                 }
-                ChimpType::ChimpN => {
+                ChimpType::ChimpN(size) => {
                     (compr_ratio, enc_speed, dec_speed) = encode(
-                        chimpn::Encoder::with_capacity(values.len()),
+                        chimpn::Encoder::with_window(size),
                         &values,
-                        ChimpType::ChimpN,
+                        ChimpType::ChimpN(size),
                     );
                 }
                 ChimpType::Gorilla => {
@@ -160,6 +169,13 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
                         ChimpType::Patas,
                     );
                 }
+                ChimpType::Decimal => {
+                    (compr_ratio, enc_speed, dec_speed) = encode(
+                        decimal::Encoder::with_capacity(values.len()),
+                        &values,
+                        ChimpType::Decimal,
+                    );
+                }
             }
             enc_vec.push(enc_speed);
             dec_vec.push(dec_speed);
@@ -182,14 +198,12 @@ pub fn test_compression(paths: &Vec<(&str, usize)>, enc_t: ChimpType) {
 // returns compression ratio, time/1000 for encoding and decoding
 #[allow(unused_variables)]
 pub fn encode(
-    mut enc: impl Encode,
+    mut enc: impl Encode<Sink = Vec<u64>>,
     values: &Vec<f64>,
     enc_t: ChimpType,
 ) -> (f64, Duration, Duration) {
     let now = Instant::now();
-    for val in values {
-        enc.encode(*val);
-    }
+    enc.encode_slice(values);
     let new_now = Instant::now();
     let (bytes, size) = enc.close();
     /*
@@ -213,9 +227,10 @@ pub fn encode(
         enc_speed,
         match enc_t {
             ChimpType::Chimp => decode(chimp::Decoder::new(bitstream), values),
-            ChimpType::ChimpN => decode(chimpn::Decoder::new(bitstream), values),
+            ChimpType::ChimpN(_) => decode(chimpn::Decoder::new(bitstream), values),
             ChimpType::Gorilla => decode(gorilla::Decoder::new(bitstream), values),
             ChimpType::Patas => decode(aligned::Decoder::new(bitstream), values),
+            ChimpType::Decimal => decode(decimal::Decoder::new(bitstream), values),
             _ => Duration::ZERO,
         },
     )
@@ -223,13 +238,8 @@ pub fn encode(
 
 #[allow(unused)]
 pub fn decode(mut dec: impl Decode, values: &Vec<f64>) -> Duration {
-    let mut vec: Vec<f64> = Vec::new();
     let now = Instant::now();
-
-    while let Ok(dec_val) = dec.get_next() {
-        vec.push(f64::from_bits(dec_val));
-    }
-
+    let vec = dec.decode_to_vec().unwrap();
     let new_now = Instant::now();
     // println!(
     //     "[decode] per 1000 values: {:?}",
@@ -240,3 +250,39 @@ pub fn decode(mut dec: impl Decode, values: &Vec<f64>) -> Duration {
 
     (new_now - now) / (vec.len() / 1000) as u32
 }
+
+// patas trades some compression for byte aligned metadata and data, this checks whether the
+// decoding speedup over chimp128 (same window, same reference lookup) is actually there
+pub fn compare_decode(paths: &Vec<(&str, usize)>) {
+    let n = 25;
+    for (path, float_idx) in paths {
+        let values = read_values(path, *float_idx);
+
+        let mut encoder = chimpn::Encoder::<f64>::new();
+        encoder.encode_slice(&values);
+        let (chimp_words, chimp_size) = encoder.close();
+        let mut encoder = aligned::Encoder::<f64>::new();
+        encoder.encode_slice(&values);
+        let (patas_words, patas_size) = encoder.close();
+
+        let mut chimp_dec = Duration::ZERO;
+        let mut patas_dec = Duration::ZERO;
+        for _ in 0..n {
+            let bitstream = InputBitStream::new(chimp_words.clone());
+            chimp_dec += decode(chimpn::Decoder::new(bitstream), &values);
+            let bitstream = InputBitStream::new(patas_words.clone());
+            patas_dec += decode(aligned::Decoder::new(bitstream), &values);
+        }
+        let (chimp_dec, patas_dec) = (chimp_dec / n, patas_dec / n);
+
+        println!("[[DATASET: {}]]", path);
+        println!(
+            "CHIMP128 Dec/1000: {:?} ({} bits/val) | PATAS Dec/1000: {:?} ({} bits/val) | speedup: {:.2}x",
+            chimp_dec,
+            chimp_size as f64 / values.len() as f64,
+            patas_dec,
+            patas_size as f64 / values.len() as f64,
+            chimp_dec.as_secs_f64() / patas_dec.as_secs_f64()
+        );
+    }
+}
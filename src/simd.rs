@@ -0,0 +1,303 @@
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Runtime detection of the simd instructions `chimp::Encoder::encode_slice` can use
+//
+// only the xors of neighbouring values are done in simd registers, writing the bits stays scalar
+// and goes through the same code as `encode`, so every path writes exactly the same stream
+
+/// Instruction set used for the xors in `encode_slice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simd {
+    Scalar,
+    Sse42,
+    Avx2,
+    /// only with the `avx512` feature, never available without it
+    Avx512,
+    Neon,
+}
+
+impl Simd {
+    pub const ALL: [Simd; 5] = [
+        Simd::Scalar,
+        Simd::Sse42,
+        Simd::Avx2,
+        Simd::Avx512,
+        Simd::Neon,
+    ];
+
+    /// the widest one this cpu has
+    pub fn detect() -> Self {
+        Simd::ALL
+            .into_iter()
+            .rev()
+            .find(|simd| simd.is_available())
+            .unwrap_or(Simd::Scalar)
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            Simd::Scalar => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Simd::Sse42 => is_x86_feature_detected!("sse4.2"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Simd::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+            Simd::Avx512 => is_x86_feature_detected!("avx512f"),
+            #[cfg(target_arch = "aarch64")]
+            Simd::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+}
+
+/// `xors[i] = bits[i] ^ bits[i + 1]`, panics if `simd` isn't available
+pub(crate) fn xor_pairs(simd: Simd, bits: &[u64], xors: &mut [u64]) {
+    assert!(simd.is_available(), "{simd:?} isn't available on this cpu");
+    assert_eq!(bits.len(), xors.len() + 1);
+    // safety: the target features were checked above and the lengths fit
+    match simd {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Simd::Sse42 => unsafe { xor_sse42(bits, xors) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Simd::Avx2 => unsafe { xor_avx2(bits, xors) },
+        #[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+        Simd::Avx512 => unsafe { xor_avx512(bits, xors) },
+        #[cfg(target_arch = "aarch64")]
+        Simd::Neon => unsafe { xor_neon(bits, xors) },
+        _ => xor_scalar(bits, xors, 0),
+    }
+}
+
+// also does the rest that doesn't fill a whole vector for the others
+#[inline(always)]
+fn xor_scalar(bits: &[u64], xors: &mut [u64], from: usize) {
+    for i in from..xors.len() {
+        xors[i] = bits[i] ^ bits[i + 1];
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse4.2")]
+unsafe fn xor_sse42(bits: &[u64], xors: &mut [u64]) {
+    let mut i = 0;
+    while i + 2 <= xors.len() {
+        let a = _mm_loadu_si128(bits[i..].as_ptr() as *const __m128i);
+        let b = _mm_loadu_si128(bits[i + 1..].as_ptr() as *const __m128i);
+        _mm_storeu_si128(xors[i..].as_mut_ptr() as *mut __m128i, _mm_xor_si128(a, b));
+        i += 2;
+    }
+    xor_scalar(bits, xors, i);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn xor_avx2(bits: &[u64], xors: &mut [u64]) {
+    let mut i = 0;
+    while i + 4 <= xors.len() {
+        let a = _mm256_loadu_si256(bits[i..].as_ptr() as *const __m256i);
+        let b = _mm256_loadu_si256(bits[i + 1..].as_ptr() as *const __m256i);
+        _mm256_storeu_si256(xors[i..].as_mut_ptr() as *mut __m256i, _mm256_xor_si256(a, b));
+        i += 4;
+    }
+    xor_scalar(bits, xors, i);
+}
+
+#[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx512f")]
+unsafe fn xor_avx512(bits: &[u64], xors: &mut [u64]) {
+    let mut i = 0;
+    while i + 8 <= xors.len() {
+        let a = _mm512_loadu_si512(bits[i..].as_ptr() as *const __m512i);
+        let b = _mm512_loadu_si512(bits[i + 1..].as_ptr() as *const __m512i);
+        _mm512_storeu_si512(xors[i..].as_mut_ptr() as *mut __m512i, _mm512_xor_si512(a, b));
+        i += 8;
+    }
+    xor_scalar(bits, xors, i);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn xor_neon(bits: &[u64], xors: &mut [u64]) {
+    let mut i = 0;
+    while i + 2 <= xors.len() {
+        let a = vld1q_u64(bits[i..].as_ptr());
+        let b = vld1q_u64(bits[i + 1..].as_ptr());
+        vst1q_u64(xors[i..].as_mut_ptr(), veorq_u64(a, b));
+        i += 2;
+    }
+    xor_scalar(bits, xors, i);
+}
+
+/// the xors of `aligned` values: `data` as little endian u64s at `offsets`, cut to the
+/// significant bytes by `shifts` (64 - 8 * bytes) and shifted back by `trails`
+pub(crate) fn gather_xors(
+    simd: Simd,
+    data: &[u8],
+    offsets: &[u64],
+    shifts: &[u64],
+    trails: &[u64],
+    xors: &mut [u64],
+) {
+    assert!(simd.is_available(), "{simd:?} isn't available on this cpu");
+    let len = xors.len();
+    assert!(offsets.len() == len && shifts.len() == len && trails.len() == len);
+    assert!(offsets.iter().all(|&offset| offset as usize + 8 <= data.len()));
+    // safety: the target features, lengths and that every load stays in data were checked above
+    match simd {
+        // sse has neither gathers nor shifts by lane, so it doesn't gain anything here
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Simd::Avx2 => unsafe { gather_avx2(data, offsets, shifts, trails, xors) },
+        #[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+        Simd::Avx512 => unsafe { gather_avx512(data, offsets, shifts, trails, xors) },
+        #[cfg(target_arch = "aarch64")]
+        Simd::Neon => unsafe { gather_neon(data, offsets, shifts, trails, xors) },
+        _ => gather_scalar(data, offsets, shifts, trails, xors, 0),
+    }
+}
+
+#[inline(always)]
+fn gather_scalar(
+    data: &[u8],
+    offsets: &[u64],
+    shifts: &[u64],
+    trails: &[u64],
+    xors: &mut [u64],
+    from: usize,
+) {
+    for i in from..xors.len() {
+        let offset = offsets[i] as usize;
+        let word = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        // a shift of 64 is a value without data bytes
+        let mask = u64::MAX.checked_shr(shifts[i] as u32).unwrap_or(0);
+        xors[i] = (word & mask) << trails[i];
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn gather_avx2(
+    data: &[u8],
+    offsets: &[u64],
+    shifts: &[u64],
+    trails: &[u64],
+    xors: &mut [u64],
+) {
+    let mut i = 0;
+    while i + 4 <= xors.len() {
+        let offsets = _mm256_loadu_si256(offsets[i..].as_ptr() as *const __m256i);
+        let shifts = _mm256_loadu_si256(shifts[i..].as_ptr() as *const __m256i);
+        let trails = _mm256_loadu_si256(trails[i..].as_ptr() as *const __m256i);
+
+        let words = _mm256_i64gather_epi64::<1>(data.as_ptr() as *const i64, offsets);
+        // shifts of 64 or more give 0 here, unlike the scalar shift
+        let masks = _mm256_srlv_epi64(_mm256_set1_epi64x(-1), shifts);
+        let xor = _mm256_sllv_epi64(_mm256_and_si256(words, masks), trails);
+        _mm256_storeu_si256(xors[i..].as_mut_ptr() as *mut __m256i, xor);
+        i += 4;
+    }
+    gather_scalar(data, offsets, shifts, trails, xors, i);
+}
+
+#[cfg(all(feature = "avx512", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx512f")]
+unsafe fn gather_avx512(
+    data: &[u8],
+    offsets: &[u64],
+    shifts: &[u64],
+    trails: &[u64],
+    xors: &mut [u64],
+) {
+    let mut i = 0;
+    while i + 8 <= xors.len() {
+        let offsets = _mm512_loadu_si512(offsets[i..].as_ptr() as *const __m512i);
+        let shifts = _mm512_loadu_si512(shifts[i..].as_ptr() as *const __m512i);
+        let trails = _mm512_loadu_si512(trails[i..].as_ptr() as *const __m512i);
+
+        let words = _mm512_i64gather_epi64::<1>(offsets, data.as_ptr() as *const i64);
+        let masks = _mm512_srlv_epi64(_mm512_set1_epi64(-1), shifts);
+        let xor = _mm512_sllv_epi64(_mm512_and_si512(words, masks), trails);
+        _mm512_storeu_si512(xors[i..].as_mut_ptr() as *mut __m512i, xor);
+        i += 8;
+    }
+    gather_scalar(data, offsets, shifts, trails, xors, i);
+}
+
+// no gathers, but the masks and shifts still work two at a time
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn gather_neon(
+    data: &[u8],
+    offsets: &[u64],
+    shifts: &[u64],
+    trails: &[u64],
+    xors: &mut [u64],
+) {
+    let mut i = 0;
+    while i + 2 <= xors.len() {
+        let words = [
+            u64::from_le_bytes(data[offsets[i] as usize..][..8].try_into().unwrap()),
+            u64::from_le_bytes(data[offsets[i + 1] as usize..][..8].try_into().unwrap()),
+        ];
+        let words = vld1q_u64(words.as_ptr());
+        // negative shifts go right, by 64 or more gives 0
+        let shifts = vnegq_s64(vreinterpretq_s64_u64(vld1q_u64(shifts[i..].as_ptr())));
+        let trails = vreinterpretq_s64_u64(vld1q_u64(trails[i..].as_ptr()));
+
+        let masks = vshlq_u64(vdupq_n_u64(u64::MAX), shifts);
+        let xor = vshlq_u64(vandq_u64(words, masks), trails);
+        vst1q_u64(xors[i..].as_mut_ptr(), xor);
+        i += 2;
+    }
+    gather_scalar(data, offsets, shifts, trails, xors, i);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gather_xors, xor_pairs, Simd};
+
+    #[test]
+    fn xor_test() {
+        assert!(Simd::Scalar.is_available());
+        assert!(Simd::detect().is_available());
+        assert!(cfg!(feature = "avx512") || !Simd::Avx512.is_available());
+
+        let bits: Vec<u64> = (0..40u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect();
+        for simd in Simd::ALL.into_iter().filter(|simd| simd.is_available()) {
+            // every length so each path also has to do a scalar rest
+            for len in 1..bits.len() {
+                let mut xors = vec![0; len - 1];
+                xor_pairs(simd, &bits[..len], &mut xors);
+                for (i, &xor) in xors.iter().enumerate() {
+                    assert_eq!(xor, bits[i] ^ bits[i + 1], "{simd:?} {len}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gather_test() {
+        let data: Vec<u8> = (0..200u32).map(|i| (i * 37 % 251) as u8).collect();
+        let offsets: Vec<u64> = (0..40).map(|i| i * 7 % 190).collect();
+        let shifts: Vec<u64> = (0..40).map(|i| 64 - 8 * (i % 9)).collect();
+        let trails: Vec<u64> = (0..40).map(|i| i * 5 % 64).collect();
+        let mut expected = vec![0; 40];
+        gather_xors(Simd::Scalar, &data, &offsets, &shifts, &trails, &mut expected);
+        assert_eq!(expected[0], 0);
+        assert_eq!(expected[1], (data[7] as u64) << 5);
+
+        for simd in Simd::ALL.into_iter().filter(|simd| simd.is_available()) {
+            for len in 0..=40 {
+                let mut xors = vec![0; len];
+                let (offsets, shifts, trails) = (&offsets[..len], &shifts[..len], &trails[..len]);
+                gather_xors(simd, &data, offsets, shifts, trails, &mut xors);
+                assert_eq!(xors, expected[..len], "{simd:?} {len}");
+            }
+        }
+    }
+}
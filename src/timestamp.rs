@@ -0,0 +1,414 @@
+use crate::bitstream::{Error, InputBitStream, OutputBitStream, Source, Words};
+use crate::{chimp, Decode, Encode};
+
+// Gorilla's delta-of-delta buckets (control bits, control length, value length)
+// the last bucket isn't in the paper, but i64 timestamps don't necessarily fit in 32 bits
+const BUCKETS: [(u64, u32, u32); 5] = [
+    (0b10, 2, 7),
+    (0b110, 3, 9),
+    (0b1110, 4, 12),
+    (0b11110, 5, 32),
+    (0b111110, 6, 64),
+];
+
+// control bits that don't belong to any bucket
+const END_OF_STREAM: u64 = 0b111111;
+const END_OF_STREAM_LEN: u32 = 6;
+
+// values in [-(2^(n-1) - 1), 2^(n-1)] are stored in n bits
+#[inline(always)]
+fn fits(dod: i64, len: u32) -> bool {
+    len == 64 || (-((1 << (len - 1)) - 1) <= dod && dod <= 1 << (len - 1))
+}
+
+#[inline(always)]
+fn sign_extend(bits: u64, len: u32) -> i64 {
+    if len < 64 && bits > 1 << (len - 1) {
+        bits as i64 - (1 << len)
+    } else {
+        bits as i64
+    }
+}
+
+/// Timestamp encoder using Gorilla's delta-of-delta compression
+///
+/// The first timestamp is stored as is, the first delta is encoded as a delta-of-delta to 0.
+#[derive(Debug)]
+pub struct Encoder {
+    first: bool,
+    curr: i64,
+    delta: i64,
+    w: OutputBitStream,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder {
+            first: true,
+            curr: 0,
+            delta: 0,
+            w: OutputBitStream::new(),
+        }
+    }
+
+    pub fn with_capacity(capa: usize) -> Self {
+        Encoder {
+            first: true,
+            curr: 0,
+            delta: 0,
+            w: OutputBitStream::with_capacity(capa),
+        }
+    }
+
+    pub fn encode(&mut self, timestamp: i64) {
+        if self.first {
+            self.first = false;
+            self.w.write_bits(timestamp as u64, 64);
+            self.curr = timestamp;
+            return;
+        }
+
+        let delta = timestamp.wrapping_sub(self.curr);
+        let dod = delta.wrapping_sub(self.delta);
+
+        if dod == 0 {
+            self.w.write_bit(0);
+        } else {
+            // last bucket always fits
+            let &(ctrl, ctrl_len, len) =
+                BUCKETS.iter().find(|(_, _, len)| fits(dod, *len)).unwrap();
+
+            self.w.write_bits(ctrl, ctrl_len);
+            self.w.write_bits(dod as u64 & (u64::MAX >> (64 - len)), len);
+        }
+
+        self.curr = timestamp;
+        self.delta = delta;
+    }
+
+    /// returns Boxed Buffer and number of bits written
+    pub fn close(self) -> (Box<[u64]>, u64) {
+        let mut this = self;
+        // empty stream stays empty
+        if !this.first {
+            this.w.write_bits(END_OF_STREAM, END_OF_STREAM_LEN);
+        }
+        this.w.close()
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<S: Source = Words> {
+    first: bool,
+    done: bool,
+    curr: i64,
+    delta: i64,
+    r: InputBitStream<S>,
+}
+
+impl Decoder {
+    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
+        Decoder::new(InputBitStream::new(buffer))
+    }
+}
+
+impl<S: Source> Decoder<S> {
+    pub fn new(r: InputBitStream<S>) -> Self {
+        Decoder {
+            first: true,
+            done: false,
+            curr: 0,
+            delta: 0,
+            r,
+        }
+    }
+
+    fn get_dod(&mut self) -> Result<i64, Error> {
+        // control bits and (except for the 64 bit bucket) the value in one peek, the control
+        // bits are the leading ones of the first END_OF_STREAM_LEN bits
+        let bits = self.r.peek_bits(64);
+        let ones = (!bits).leading_zeros().min(END_OF_STREAM_LEN);
+
+        if ones == END_OF_STREAM_LEN {
+            self.r.consume(END_OF_STREAM_LEN)?;
+            self.done = true;
+            return Err(Error::EOF);
+        }
+        if ones == 0 {
+            self.r.consume(1)?;
+            return Ok(0);
+        }
+
+        let (_, control_len, len) = BUCKETS[ones as usize - 1];
+        if control_len + len > 64 {
+            self.r.consume(control_len)?;
+            return Ok(sign_extend(self.r.read_bits(len)?, len));
+        }
+        self.r.consume(control_len + len)?;
+        Ok(sign_extend((bits << control_len) >> (64 - len), len))
+    }
+
+    pub fn get_next(&mut self) -> Result<i64, Error> {
+        if self.done {
+            return Err(Error::EOF);
+        }
+
+        if self.first {
+            self.first = false;
+            // nothing was encoded
+            if self.r.is_empty()? {
+                self.done = true;
+                return Err(Error::EOF);
+            }
+            self.curr = self.r.read_bits(64)? as i64;
+        } else {
+            self.delta = self.delta.wrapping_add(self.get_dod()?);
+            self.curr = self.curr.wrapping_add(self.delta);
+        }
+
+        Ok(self.curr)
+    }
+}
+
+impl<S: Source> Decode<i64> for Decoder<S> {
+    fn get_next(&mut self) -> Result<u64, Error> {
+        self.get_next().map(|ts| ts as u64)
+    }
+
+    // the first timestamp goes through get_next, after that only the deltas of deltas
+    fn decode_into(&mut self, out: &mut [i64]) -> Result<usize, Error> {
+        let mut n = 0;
+        if self.first && !out.is_empty() {
+            match self.get_next() {
+                Ok(ts) => out[0] = ts,
+                Err(Error::EOF) => return Ok(0),
+                Err(err) => return Err(err),
+            }
+            n = 1;
+        }
+
+        while n < out.len() && !self.done {
+            match self.get_dod() {
+                Ok(dod) => {
+                    self.delta = self.delta.wrapping_add(dod);
+                    self.curr = self.curr.wrapping_add(self.delta);
+                    out[n] = self.curr;
+                    n += 1;
+                }
+                Err(Error::EOF) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Source> Iterator for Decoder<S> {
+    type Item = Result<i64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), |ts| ts)
+    }
+}
+
+/// Encodes (timestamp, value) pairs into a timestamp stream and a separate value stream
+#[derive(Debug)]
+pub struct PointEncoder<E = chimp::Encoder> {
+    timestamps: Encoder,
+    values: E,
+}
+
+impl<E: Encode<Sink = Vec<u64>>> PointEncoder<E> {
+    pub fn new(values: E) -> Self {
+        PointEncoder {
+            timestamps: Encoder::new(),
+            values,
+        }
+    }
+
+    pub fn encode(&mut self, timestamp: i64, value: f64) {
+        self.timestamps.encode(timestamp);
+        self.values.encode(value);
+    }
+
+    /// returns the timestamp and value buffers with their number of bits written
+    #[allow(clippy::type_complexity)]
+    pub fn close(self) -> ((Box<[u64]>, u64), (Box<[u64]>, u64)) {
+        (self.timestamps.close(), self.values.close())
+    }
+}
+
+pub struct PointDecoder<D = chimp::Decoder, S: Source = Words> {
+    timestamps: Decoder<S>,
+    values: D,
+}
+
+impl<D: Decode, S: Source> PointDecoder<D, S> {
+    pub fn new(timestamps: InputBitStream<S>, values: D) -> Self {
+        PointDecoder {
+            timestamps: Decoder::new(timestamps),
+            values,
+        }
+    }
+
+    pub fn get_next(&mut self) -> Result<(i64, f64), Error> {
+        let timestamp = self.timestamps.get_next()?;
+        // both streams have to end at the same time
+        let value = match self.values.get_next() {
+            Ok(value) => f64::from_bits(value),
+            Err(Error::EOF) => return Err(Error::Truncated),
+            Err(err) => return Err(err),
+        };
+        Ok((timestamp, value))
+    }
+}
+
+impl<D: Decode, S: Source> Iterator for PointDecoder<D, S> {
+    type Item = Result<(i64, f64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), |point| point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Encoder, PointDecoder, PointEncoder};
+    use crate::bitstream::InputBitStream;
+    use crate::{chimp, gorilla, Decode};
+
+    fn round_trip(timestamps: &[i64]) {
+        let mut encoder = Encoder::new();
+
+        for &ts in timestamps {
+            encoder.encode(ts);
+        }
+
+        let (bytes, _) = encoder.close();
+        let mut decoder = Decoder::new(InputBitStream::from_slice(&bytes));
+        let mut datapoints = Vec::new();
+
+        while let Ok(ts) = decoder.get_next() {
+            datapoints.push(ts);
+        }
+
+        assert_eq!(datapoints, timestamps);
+        assert_eq!(Decoder::from_buffer(bytes).decode_to_vec(), Ok(timestamps.to_vec()));
+    }
+
+    #[test]
+    fn simple_test() {
+        // regular 10s interval with some jitter
+        round_trip(&[
+            1_673_000_000,
+            1_673_000_010,
+            1_673_000_020,
+            1_673_000_030,
+            1_673_000_041,
+            1_673_000_050,
+            1_673_000_060,
+            1_673_000_060,
+            1_673_000_070,
+        ]);
+    }
+
+    #[test]
+    fn bucket_test() {
+        // deltas of deltas hitting the bounds of every bucket
+        let mut timestamps = vec![0i64];
+        for dod in [
+            0,
+            1,
+            -1,
+            64,
+            -63,
+            65,
+            -64,
+            256,
+            -255,
+            257,
+            -256,
+            2048,
+            -2047,
+            2049,
+            -2048,
+            i32::MAX as i64,
+            i32::MIN as i64 + 1,
+            i32::MAX as i64 + 2,
+            i32::MIN as i64,
+        ] {
+            let last = timestamps.len() - 1;
+            let delta = if last == 0 {
+                0
+            } else {
+                timestamps[last] - timestamps[last - 1]
+            };
+            timestamps.push(timestamps[last] + delta + dod);
+        }
+        round_trip(&timestamps);
+    }
+
+    #[test]
+    fn extremes_test() {
+        round_trip(&[i64::MIN, i64::MAX, 0, i64::MIN, -1, i64::MAX, i64::MAX]);
+        round_trip(&[42]);
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn point_test() {
+        let points: Vec<(i64, f64)> = [
+            (1_673_000_000_000, 49.4),
+            (1_673_000_010_000, 48.8),
+            (1_673_000_020_000, 46.4),
+            (1_673_000_030_000, f64::NAN),
+            (1_673_000_040_000, 48.7),
+            (1_673_000_050_000, 48.9),
+        ]
+        .to_vec();
+
+        let mut encoder = PointEncoder::new(chimp::Encoder::new());
+        for &(ts, val) in &points {
+            encoder.encode(ts, val);
+        }
+
+        let (timestamps, values) = encoder.close();
+        let mut decoder = PointDecoder::new(
+            InputBitStream::new(timestamps.0),
+            chimp::Decoder::new(InputBitStream::new(values.0)),
+        );
+        let mut datapoints = Vec::new();
+
+        while let Ok((ts, val)) = decoder.get_next() {
+            datapoints.push((ts, val.to_bits()));
+        }
+
+        let expected: Vec<(i64, u64)> = points.iter().map(|(t, v)| (*t, v.to_bits())).collect();
+        assert_eq!(datapoints, expected);
+    }
+
+    #[test]
+    fn gorilla_point_test() {
+        let mut encoder = PointEncoder::new(gorilla::Encoder::new());
+        for i in 0..1000 {
+            encoder.encode(i * 60, (i % 7) as f64 * 0.5);
+        }
+
+        let (timestamps, values) = encoder.close();
+        let mut decoder = PointDecoder::new(
+            InputBitStream::new(timestamps.0),
+            gorilla::Decoder::new(InputBitStream::new(values.0)),
+        );
+
+        for i in 0..1000 {
+            assert_eq!(decoder.get_next(), Ok((i * 60, (i % 7) as f64 * 0.5)));
+        }
+        assert!(decoder.get_next().is_err());
+    }
+}
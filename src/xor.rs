@@ -0,0 +1,140 @@
+use crate::bitstream::{Error, InputBitStream, Sink, Source, Words};
+use crate::{gorilla, Decode, Encode, Value64};
+use std::marker::PhantomData;
+
+// Gorilla's xor compression for 64 bit integers that don't change by small steps, like ids,
+// hashes or bit flags, but still share a lot of bits with the value before them
+//
+// gorilla never looks at anything but the bit patterns, so this is just the gorilla codec with
+// the integers passed through as f64 bits. the stream is exactly what gorilla writes for f64s
+
+#[derive(Debug)]
+pub struct Encoder<T: Value64 = u64, S: Sink = Vec<u64>> {
+    inner: gorilla::Encoder<f64, S>,
+    value: PhantomData<T>,
+}
+
+impl<T: Value64> Encoder<T> {
+    pub fn new() -> Self {
+        Encoder::with_sink(Vec::new())
+    }
+
+    pub fn with_capacity(capa: usize) -> Self {
+        Encoder::with_sink(Vec::with_capacity(capa))
+    }
+}
+
+impl<T: Value64> Default for Encoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Value64, S: Sink> Encoder<T, S> {
+    /// e.g. `WriteSink` to stream the compressed words to a writer
+    pub fn with_sink(sink: S) -> Self {
+        Encoder {
+            inner: gorilla::Encoder::with_sink(sink),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T: Value64, S: Sink> Encode<T> for Encoder<T, S> {
+    type Sink = S;
+
+    fn encode(&mut self, value: T) {
+        self.inner.encode(f64::from_bits(value.to_bits64()));
+    }
+
+    fn close(self) -> (S::Output, u64) {
+        self.inner.close()
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<T: Value64 = u64, S: Source = Words> {
+    inner: gorilla::Decoder<f64, S>,
+    value: PhantomData<T>,
+}
+
+impl<T: Value64> Decoder<T> {
+    pub fn from_buffer(buffer: Box<[u64]>) -> Self {
+        Decoder::new(InputBitStream::new(buffer))
+    }
+}
+
+impl<T: Value64, S: Source> Decoder<T, S> {
+    pub fn new(r: InputBitStream<S>) -> Self {
+        Decoder {
+            inner: gorilla::Decoder::new(r),
+            value: PhantomData,
+        }
+    }
+
+    pub fn get_next(&mut self) -> Result<u64, Error> {
+        self.inner.get_next()
+    }
+}
+
+impl<T: Value64, S: Source> Decode<T> for Decoder<T, S> {
+    fn get_next(&mut self) -> Result<u64, Error> {
+        self.get_next()
+    }
+
+    fn decode_into(&mut self, out: &mut [T]) -> Result<usize, Error> {
+        self.inner.decode_as(out)
+    }
+}
+
+impl<T: Value64, S: Source> Iterator for Decoder<T, S> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        crate::next_item(self.get_next(), T::from_bits64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Encoder};
+    use crate::bitstream::Error;
+    use crate::{delta, Decode, Encode, Value, Value64};
+
+    fn encode<T: Value>(mut encoder: impl Encode<T, Sink = Vec<u64>>, values: &[T]) -> u64 {
+        for &val in values {
+            encoder.encode(val);
+        }
+        encoder.close().1
+    }
+
+    fn round_trip<T: Value64>(values: &[T]) -> u64 {
+        let mut encoder = Encoder::<T>::with_capacity(values.len());
+        for &val in values {
+            encoder.encode(val);
+        }
+        let (words, len) = encoder.close();
+
+        let mut decoder = Decoder::<T>::from_buffer(words);
+        assert_eq!(decoder.decode_to_vec(), Ok(values.to_vec()));
+        assert_eq!(decoder.get_next(), Err(Error::EOF));
+        len
+    }
+
+    #[test]
+    fn simple_test() {
+        round_trip(&[0u64, u64::MAX, 1, 1, 1 << 63, 0xdead_beef, 0xdead_beef, 0]);
+        round_trip(&[i64::MIN, -1, 0, i64::MAX, 42]);
+        round_trip::<u64>(&[]);
+    }
+
+    #[test]
+    fn ids_test() {
+        // ids with a fixed prefix and a few changing bits in the middle, no small deltas
+        let ids: Vec<u64> = (0..1000u64)
+            .map(|i| 0xabcd_0000_0000_0000 | ((i * 0x9e37) & 0xffff) << 20 | 0x1234)
+            .collect();
+        let len = round_trip(&ids);
+        assert!(len < encode(delta::Encoder::new(), &ids), "{len}");
+    }
+}